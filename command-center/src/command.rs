@@ -0,0 +1,340 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ff::{
+    Anchor, Position, Size, TextAlign, TextBackground, TextEffects, TextLayout, TextShadow,
+    TextStroke, Transform,
+};
+
+/// A single line typed by the user, its first word being the command.
+#[derive(Parser)]
+#[command(multicall = true)]
+pub struct Line {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Create a party and print an invitation link to it
+    Create,
+
+    /// Join a party with an invitation link
+    Join { invitation_link: String },
+
+    /// Add a static image (PNG, JPEG, ...) to the next batch of overlays
+    Image {
+        path: PathBuf,
+        #[command(flatten)]
+        placement: Placement,
+    },
+
+    /// Add an animated GIF, APNG or WebP to the next batch of overlays
+    Gif {
+        path: PathBuf,
+        #[command(flatten)]
+        placement: Placement,
+    },
+
+    /// Add some text to the next batch of overlays
+    Text {
+        text: String,
+        /// Style parts of the text with `**bold**`, `*italic*` and `{#rrggbb colored}`, `\` escaping the next character
+        #[arg(long)]
+        markup: bool,
+        /// Font size, in pixels
+        #[arg(long, default_value_t = 48)]
+        size: u32,
+        /// Color of the text, as `#rrggbb` or `#rrggbbaa`
+        #[arg(long, default_value = "#ffffff", value_parser = parse_color)]
+        color: [u8; 4],
+        /// Family of the font, the splash-screens' default font is used when they do not have it
+        #[arg(long)]
+        font: Option<String>,
+        /// Font file (TTF / OTF) containing the `--font` family, sent along with the text
+        #[arg(long, requires = "font")]
+        font_file: Option<PathBuf>,
+        #[command(flatten)]
+        layout: Layout,
+        #[command(flatten)]
+        effects: Effects,
+        #[command(flatten)]
+        placement: Placement,
+    },
+
+    /// Send the batch of overlays to every splash-screen of the party
+    Send {
+        /// Time after which every overlay is removed, once fired
+        #[arg(long, default_value_t = 5000)]
+        timeout_ms: u32,
+    },
+
+    /// Wait for every splash-screen to receive and rasterize the overlays
+    Wait,
+
+    /// Show the overlays on every splash-screen
+    Fire,
+
+    /// Leave the party and disconnect
+    Quit,
+}
+
+/// How the lines of a text overlay are laid out.
+#[derive(Args)]
+pub struct Layout {
+    /// Width in pixels after which lines wrap, lines only break on `\n` by default
+    #[arg(long)]
+    pub max_width: Option<u32>,
+    /// Alignment of the lines relative to each other
+    #[arg(long, value_enum, default_value_t = Align::Left)]
+    pub align: Align,
+    /// Distance between two lines, as a multiple of the font size
+    #[arg(long, default_value_t = 1.2)]
+    pub line_height: f32,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+impl Layout {
+    pub fn layout(&self) -> TextLayout {
+        TextLayout {
+            max_width: self.max_width,
+            align: match self.align {
+                Align::Left => TextAlign::Left,
+                Align::Center => TextAlign::Center,
+                Align::Right => TextAlign::Right,
+                Align::Justify => TextAlign::Justify,
+            },
+            line_height: self.line_height,
+        }
+    }
+}
+
+/// Decorations keeping a text overlay readable on any background.
+#[derive(Args)]
+pub struct Effects {
+    /// Width in pixels of an outline around the glyphs
+    #[arg(long)]
+    pub stroke: Option<f32>,
+    #[arg(long, default_value = "#000000", value_parser = parse_color)]
+    pub stroke_color: [u8; 4],
+    /// Offset in pixels of a drop shadow, as `x,y`
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_offset)]
+    pub shadow: Option<(i32, i32)>,
+    /// Blur radius of the drop shadow in pixels
+    #[arg(long, default_value_t = 4.0)]
+    pub shadow_blur: f32,
+    #[arg(long, default_value = "#000000aa", value_parser = parse_color)]
+    pub shadow_color: [u8; 4],
+    /// Color of a box behind the text
+    #[arg(long, value_parser = parse_color)]
+    pub background: Option<[u8; 4]>,
+    /// Space in pixels between the text and the edges of its box
+    #[arg(long, default_value_t = 8)]
+    pub padding: u32,
+    /// Radius in pixels of the corners of the box
+    #[arg(long, default_value_t = 8.0)]
+    pub corner_radius: f32,
+}
+
+impl Effects {
+    pub fn effects(&self) -> TextEffects {
+        TextEffects {
+            stroke: self.stroke.map(|width| TextStroke {
+                width,
+                color: self.stroke_color,
+            }),
+            shadow: self.shadow.map(|(offset_x, offset_y)| TextShadow {
+                offset_x,
+                offset_y,
+                blur_radius: self.shadow_blur,
+                color: self.shadow_color,
+            }),
+            background: self.background.map(|color| TextBackground {
+                padding: self.padding,
+                corner_radius: self.corner_radius,
+                color,
+            }),
+        }
+    }
+}
+
+/// Where and when an overlay is shown.
+#[derive(Args)]
+pub struct Placement {
+    /// Distance from the left of the screen, in pixels
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub left: i32,
+    /// Distance from the top of the screen, in pixels
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub top: i32,
+    /// Overlays with a higher z-index are drawn on top of the others
+    #[arg(long, default_value_t = 0)]
+    pub z_index: u32,
+    /// Point of the screen `--left-percent` and `--top-percent` start from, same values as `--anchor`
+    #[arg(long, value_parser = parse_anchor, conflicts_with_all = ["left", "top"])]
+    pub origin: Option<Anchor>,
+    /// Distance from the origin as a percentage of the screen width, instead of `--left`
+    #[arg(long, allow_negative_numbers = true, conflicts_with_all = ["left", "top"])]
+    pub left_percent: Option<f32>,
+    /// Distance from the origin as a percentage of the screen height, instead of `--top`
+    #[arg(long, allow_negative_numbers = true, conflicts_with_all = ["left", "top"])]
+    pub top_percent: Option<f32>,
+    /// Delay after the overlays are fired before this one appears
+    #[arg(long, default_value_t = 0)]
+    pub start_ms: u32,
+    /// How long this overlay is shown, until the end of the batch by default
+    #[arg(long)]
+    pub duration_ms: Option<u32>,
+
+    /// Multiply the size of the overlay, e.g. 0.5 to halve it
+    #[arg(long, conflicts_with_all = ["width", "height", "height_percent"])]
+    pub scale: Option<f32>,
+    /// Width of the overlay in pixels, keeping its aspect ratio unless `--height` is given too
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Height of the overlay in pixels, keeping its aspect ratio unless `--width` is given too
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// Height of the overlay as a percentage of the screen height, keeping its aspect ratio
+    #[arg(long, conflicts_with_all = ["width", "height"])]
+    pub height_percent: Option<f32>,
+    /// Clockwise rotation, in degrees
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub rotate: f32,
+    /// From 0 (invisible) to 1 (opaque)
+    #[arg(long, default_value_t = 1.0)]
+    pub opacity: f32,
+    /// Point of the overlay placed at `--left` and `--top`, and around which it rotates.
+    /// Either `top-left`, `top`, `top-right`, `left`, `center`, `right`, `bottom-left`, `bottom`, `bottom-right` or `x,y` between 0 and 1
+    #[arg(long, default_value = "top-left", value_parser = parse_anchor)]
+    pub anchor: Anchor,
+}
+
+impl Placement {
+    /// Relative position of the overlay, `None` when it is placed in pixels with `--left` and `--top`.
+    pub fn position(&self) -> Option<Position> {
+        if self.origin.is_none() && self.left_percent.is_none() && self.top_percent.is_none() {
+            return None;
+        }
+
+        Some(Position::Relative {
+            origin: self.origin.unwrap_or(Anchor::TOP_LEFT),
+            left: self.left_percent.unwrap_or(0.0) / 100.0,
+            top: self.top_percent.unwrap_or(0.0) / 100.0,
+        })
+    }
+
+    pub fn transform(&self) -> Transform {
+        let size = match (self.scale, self.width, self.height, self.height_percent) {
+            (Some(factor), ..) => Size::Scale(factor),
+            (None, _, _, Some(percent)) => Size::CanvasHeight(percent / 100.0),
+            (None, None, None, None) => Size::Native,
+            (None, width, height, None) => Size::Pixels { width, height },
+        };
+
+        Transform {
+            size,
+            rotation_deg: self.rotate,
+            opacity: self.opacity,
+            anchor: self.anchor,
+        }
+    }
+}
+
+/// Parse a `#rrggbb` or `#rrggbbaa` color into RGBA components.
+pub(crate) fn parse_color(color: &str) -> Result<[u8; 4], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err("expected a color as `#rrggbb` or `#rrggbbaa`".to_string());
+    }
+
+    let mut rgba = [255; 4];
+    for (i, component) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+        *component = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("`{color}` is not an hexadecimal color"))?;
+    }
+    Ok(rgba)
+}
+
+/// Parse an offset in pixels given as `x,y`.
+fn parse_offset(offset: &str) -> Result<(i32, i32), String> {
+    let parse = |value: &str| value.trim().parse::<i32>().ok();
+    offset
+        .split_once(',')
+        .and_then(|(x, y)| Some((parse(x)?, parse(y)?)))
+        .ok_or_else(|| format!("expected an offset as `x,y`, got `{offset}`"))
+}
+
+/// Parse an anchor either by its name (`center`, `bottom-right`, ...) or as `x,y`.
+fn parse_anchor(anchor: &str) -> Result<Anchor, String> {
+    let named = match anchor {
+        "top-left" => Some(Anchor::TOP_LEFT),
+        "top" => Some(Anchor::TOP),
+        "top-right" => Some(Anchor::TOP_RIGHT),
+        "left" => Some(Anchor::LEFT),
+        "center" => Some(Anchor::CENTER),
+        "right" => Some(Anchor::RIGHT),
+        "bottom-left" => Some(Anchor::BOTTOM_LEFT),
+        "bottom" => Some(Anchor::BOTTOM),
+        "bottom-right" => Some(Anchor::BOTTOM_RIGHT),
+        _ => None,
+    };
+    if let Some(anchor) = named {
+        return Ok(anchor);
+    }
+
+    let parse = |value: &str| value.trim().parse::<f32>().ok();
+    anchor
+        .split_once(',')
+        .and_then(|(x, y)| {
+            Some(Anchor {
+                x: parse(x)?,
+                y: parse(y)?,
+            })
+        })
+        .ok_or_else(|| {
+            format!(
+                "`{anchor}` is neither a named anchor (`center`, `bottom-right`, ...) nor `x,y`"
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(args: &str) -> Result<Placement, clap::Error> {
+        let line = Line::try_parse_from(format!("image cat.png {args}").split_whitespace())?;
+        match line.command {
+            Command::Image { placement, .. } => Ok(placement),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn pixels_and_percentages_cannot_be_mixed() {
+        assert!(placement("--left 100 --top-percent 10").is_err());
+        assert!(placement("--top 100 --left-percent 10").is_err());
+        assert!(placement("--left 100 --origin center").is_err());
+
+        assert!(
+            placement("--left 100 --top 50")
+                .unwrap()
+                .position()
+                .is_none()
+        );
+        assert!(
+            placement("--left-percent 10 --top-percent 5")
+                .unwrap()
+                .position()
+                .is_some()
+        );
+    }
+}
@@ -1,3 +1,162 @@
-fn main() {
-    println!("Hello, world!");
+use std::io::Write;
+
+use clap::Parser;
+use ff::{DisplayOptions, Font, Media, MediaRef, Overlay};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{
+    command::{Command, Line},
+    session::Session,
+};
+
+mod command;
+mod markup;
+mod session;
+
+/// Server to connect to when none is given.
+const DEFAULT_ADDRESS: &str = "ws://127.0.0.1:9001";
+
+/// Send overlays to the splash-screens of a party.
+///
+/// Commands are read from the standard input, one per line (try `help`).
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Address of the relay server
+    #[arg(long, default_value = DEFAULT_ADDRESS)]
+    server: String,
+
+    /// First command to run, e.g. `create` or `join <invitation-link>`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Run a single command, returns `false` once the user wants to quit.
+async fn run_command(session: &mut Session, command: Command) -> anyhow::Result<bool> {
+    match command {
+        Command::Create => session.create_party().await?,
+        Command::Join { invitation_link } => session.join(invitation_link).await?,
+        Command::Image { path, placement } => {
+            let data = std::fs::read(&path)?;
+            let overlay = Overlay::Image {
+                media: Media::Ref(MediaRef::of(&data)),
+                offset_left: placement.left,
+                offset_top: placement.top,
+                z_index: placement.z_index,
+                start_ms: placement.start_ms,
+                duration_ms: placement.duration_ms,
+                transform: placement.transform(),
+                position: placement.position(),
+            };
+            session.add_overlay(overlay, Some(data));
+        }
+        Command::Gif { path, placement } => {
+            let data = std::fs::read(&path)?;
+            let overlay = Overlay::AnimatedImage {
+                media: Media::Ref(MediaRef::of(&data)),
+                offset_left: placement.left,
+                offset_top: placement.top,
+                z_index: placement.z_index,
+                start_ms: placement.start_ms,
+                duration_ms: placement.duration_ms,
+                transform: placement.transform(),
+                position: placement.position(),
+            };
+            session.add_overlay(overlay, Some(data));
+        }
+        Command::Text {
+            text,
+            markup,
+            size,
+            color,
+            font,
+            font_file,
+            layout,
+            effects,
+            placement,
+        } => {
+            let data = font_file.map(std::fs::read).transpose()?;
+            let font = font.map(|family| Font {
+                family,
+                data: data.as_deref().map(|data| Media::Ref(MediaRef::of(data))),
+            });
+            let spans = if markup {
+                markup::parse(&text)?
+            } else {
+                Vec::new()
+            };
+            // Only the spans are rendered, the text is left without markup for whoever reads it
+            let text = if markup {
+                spans.iter().map(|span| span.text.as_str()).collect()
+            } else {
+                text
+            };
+            let overlay = Overlay::Text {
+                text,
+                spans,
+                size,
+                color,
+                layout: layout.layout(),
+                font,
+                effects: effects.effects(),
+                offset_left: placement.left,
+                offset_top: placement.top,
+                z_index: placement.z_index,
+                start_ms: placement.start_ms,
+                duration_ms: placement.duration_ms,
+                transform: placement.transform(),
+                position: placement.position(),
+            };
+            session.add_overlay(overlay, data);
+        }
+        Command::Send { timeout_ms } => {
+            session.send_overlays(DisplayOptions { timeout_ms }).await?
+        }
+        Command::Wait => session.wait().await?,
+        Command::Fire => session.fire().await?,
+        Command::Quit => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let mut session = Session::connect(&cli.server).await?;
+
+    if let Some(command) = cli.command
+        && !run_command(&mut session, command).await?
+    {
+        return Ok(());
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+
+        let Some(words) = shlex::split(&line) else {
+            eprintln!("Unbalanced quotes");
+            continue;
+        };
+        if words.is_empty() {
+            continue;
+        }
+
+        match Line::try_parse_from(words) {
+            Ok(Line { command }) => match run_command(&mut session, command).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => eprintln!("Error: {err:#}"),
+            },
+            // Also covers `help`
+            Err(err) => err.print()?,
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,140 @@
+use ff::TextSpan;
+
+use crate::command::parse_color;
+
+/// Weight of the `**bold**` runs.
+const BOLD_WEIGHT: u16 = 700;
+
+/// Split some text into runs of styled text, following a small markup :
+/// - `**bold**`
+/// - `*italic*`
+/// - `{#ff0000 colored}`, with any color accepted by `--color`
+/// - `\` escapes the next character
+///
+/// Styles can be nested, e.g. `{#ff0000 **BONK**} you`.
+pub fn parse(markup: &str) -> anyhow::Result<Vec<TextSpan>> {
+    let mut parser = Parser::default();
+    let mut chars = markup.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => parser.text.push(chars.next().unwrap_or('\\')),
+            '*' if chars.next_if_eq(&'*').is_some() => {
+                parser.flush();
+                parser.bold = !parser.bold;
+            }
+            '*' => {
+                parser.flush();
+                parser.italic = !parser.italic;
+            }
+            '{' => {
+                let color: String = chars.by_ref().take_while(|c| !c.is_whitespace()).collect();
+                let color =
+                    parse_color(&color).map_err(|err| anyhow::anyhow!("{err}, after `{{`"))?;
+                parser.flush();
+                parser.colors.push(color);
+            }
+            '}' => {
+                parser.flush();
+                if parser.colors.pop().is_none() {
+                    anyhow::bail!("`}}` closes nothing, escape it as `\\}}`");
+                }
+            }
+            c => parser.text.push(c),
+        }
+    }
+
+    if parser.bold || parser.italic {
+        anyhow::bail!("unclosed `*`, escape it as `\\*`");
+    }
+    if !parser.colors.is_empty() {
+        anyhow::bail!("unclosed `{{`, escape it as `\\{{`");
+    }
+    parser.flush();
+    Ok(parser.spans)
+}
+
+/// Style at the current position of the markup, along with what was parsed so far.
+#[derive(Default)]
+struct Parser {
+    spans: Vec<TextSpan>,
+    /// Text of the upcoming span, which has the current style.
+    text: String,
+    bold: bool,
+    italic: bool,
+    /// Colors of the `{}` that are currently open, the last one applies.
+    colors: Vec<[u8; 4]>,
+}
+
+impl Parser {
+    /// End the current span, before the style changes.
+    fn flush(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+
+        self.spans.push(TextSpan {
+            text: std::mem::take(&mut self.text),
+            color: self.colors.last().copied(),
+            weight: self.bold.then_some(BOLD_WEIGHT),
+            italic: self.italic,
+            ..TextSpan::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str) -> TextSpan {
+        TextSpan {
+            text: text.to_string(),
+            ..TextSpan::default()
+        }
+    }
+
+    #[test]
+    fn plain_text_is_a_single_span() {
+        assert_eq!(parse("hello world").unwrap(), vec![span("hello world")]);
+    }
+
+    #[test]
+    fn styles_can_be_nested() {
+        let red = Some([255, 0, 0, 255]);
+        assert_eq!(
+            parse("{#ff0000 **BONK** *you*} !").unwrap(),
+            vec![
+                TextSpan {
+                    color: red,
+                    weight: Some(BOLD_WEIGHT),
+                    ..span("BONK")
+                },
+                TextSpan {
+                    color: red,
+                    ..span(" ")
+                },
+                TextSpan {
+                    color: red,
+                    italic: true,
+                    ..span("you")
+                },
+                span(" !"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_characters_are_kept() {
+        assert_eq!(parse(r"5 \* 3 \{\}").unwrap(), vec![span("5 * 3 {}")]);
+    }
+
+    #[test]
+    fn unbalanced_markup_is_rejected() {
+        assert!(parse("5 * 3").is_err());
+        assert!(parse("**bold").is_err());
+        assert!(parse("{#ff0000 red").is_err());
+        assert!(parse("red}").is_err());
+        assert!(parse("{red text}").is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ff::{
+    ClientKind, ClientMessage, ClientMessageType, Codec, DisplayOptions, MAX_CONCURRENT_UPLOADS,
+    MediaRef, OutgoingTransfer, Overlay, ServerMessage, ServerMessageType, Version,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream,
+    tungstenite::{Message, protocol::WebSocketConfig},
+};
+use uuid::Uuid;
+
+/// Progress of the last batch of overlays sent, see `ServerMessageType::OverlaysFullAck`.
+#[derive(Default)]
+struct BatchProgress {
+    downloaded: bool,
+    rasterized: bool,
+}
+
+/// Connection of the command-center to the relay server, along with what was done through it.
+pub struct Session {
+    codec: Codec,
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+
+    party_id: Option<Uuid>,
+    /// Overlays waiting to be sent as a single batch.
+    overlays: Vec<Overlay>,
+    /// Data of the media referenced by `overlays`.
+    media: HashMap<MediaRef, Arc<Vec<u8>>>,
+    /// Last batch sent, until it is fired.
+    batch: Option<BatchProgress>,
+}
+
+impl Session {
+    /// Connect to the server and introduce ourselves as a command-center.
+    pub async fn connect(address: &str) -> anyhow::Result<Self> {
+        let codec = Codec::default();
+        let config = WebSocketConfig::default().max_message_size(Some(codec.max_frame_size()));
+        let (socket, _) =
+            tokio_tungstenite::connect_async_with_config(address, Some(config), false).await?;
+
+        let mut session = Self {
+            codec,
+            socket,
+            party_id: None,
+            overlays: Vec::new(),
+            media: HashMap::new(),
+            batch: None,
+        };
+
+        session
+            .send(ClientMessageType::Hello {
+                kind: ClientKind::CommandCenter,
+            })
+            .await?;
+        loop {
+            match session.next_message().await? {
+                ServerMessageType::Welcome { client_id } => {
+                    println!("Connected as {client_id}");
+                    return Ok(session);
+                }
+                ServerMessageType::Rejected { reason } => {
+                    anyhow::bail!("Rejected by the server: {reason}")
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub async fn create_party(&mut self) -> anyhow::Result<()> {
+        self.send(ClientMessageType::CreateParty).await?;
+        let party_id = loop {
+            if let ServerMessageType::PartyCreated { party_id } = self.next_message().await? {
+                break party_id;
+            }
+        };
+        self.party_id = Some(party_id);
+        println!("Created party {party_id}");
+
+        self.send(ClientMessageType::CreateInvationLink { party_id })
+            .await?;
+        loop {
+            if let ServerMessageType::InvitationLinkCreated {
+                invitation_link, ..
+            } = self.next_message().await?
+            {
+                println!("Invitation link: {invitation_link}");
+                return Ok(());
+            }
+        }
+    }
+
+    pub async fn join(&mut self, invitation_link: String) -> anyhow::Result<()> {
+        self.send(ClientMessageType::JoinParty { invitation_link })
+            .await?;
+        loop {
+            if let ServerMessageType::JoinAccepted { party_id } = self.next_message().await? {
+                self.party_id = Some(party_id);
+                println!("Joined party {party_id}");
+                return Ok(());
+            }
+        }
+    }
+
+    /// Add an overlay to the next batch, along with the data of its media if any.
+    pub fn add_overlay(&mut self, overlay: Overlay, media: Option<Vec<u8>>) {
+        if let Some(data) = media {
+            self.media.insert(MediaRef::of(&data), Arc::new(data));
+        }
+        self.overlays.push(overlay);
+    }
+
+    /// Upload the media of the next batch, and send it to every splash-screen of the party.
+    pub async fn send_overlays(&mut self, options: DisplayOptions) -> anyhow::Result<()> {
+        if self.party_id.is_none() {
+            anyhow::bail!("Create or join a party first");
+        }
+        if self.overlays.is_empty() {
+            anyhow::bail!("Add some overlays first");
+        }
+
+        self.upload_media().await?;
+
+        let overlays = std::mem::take(&mut self.overlays);
+        let count = overlays.len();
+        self.send(ClientMessageType::Overlays { overlays, options })
+            .await?;
+        self.media.clear();
+        self.batch = Some(BatchProgress::default());
+        println!("Sent {count} overlay(s)");
+        Ok(())
+    }
+
+    /// Wait for every splash-screen to be ready to show the last batch.
+    pub async fn wait(&mut self) -> anyhow::Result<()> {
+        if self.batch.is_none() {
+            anyhow::bail!("Send some overlays first");
+        }
+
+        let started_at = Instant::now();
+        let mut ticker = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            let Some(batch) = &self.batch else {
+                anyhow::bail!("The batch of overlays was dropped");
+            };
+            print!(
+                "\rReceived {} | Rasterized {} | {:.1}s",
+                checkbox(batch.downloaded),
+                checkbox(batch.rasterized),
+                started_at.elapsed().as_secs_f32()
+            );
+            std::io::stdout().flush()?;
+            if batch.downloaded && batch.rasterized {
+                break;
+            }
+
+            tokio::select! {
+                _ = ticker.tick() => {}
+                message = self.next_message() => {
+                    if let Err(err) = message {
+                        println!();
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        println!();
+        println!("Every splash-screen is ready, the overlays can be fired");
+        Ok(())
+    }
+
+    pub async fn fire(&mut self) -> anyhow::Result<()> {
+        match &self.batch {
+            Some(batch) if batch.rasterized => {}
+            Some(_) => anyhow::bail!("Not every splash-screen is ready yet, `wait` for them"),
+            None => anyhow::bail!("Send some overlays first"),
+        }
+
+        self.send(ClientMessageType::Fire).await?;
+        self.batch = None;
+        println!("Fired!");
+        Ok(())
+    }
+
+    /// Upload the media of the next batch that the server does not have yet.
+    async fn upload_media(&mut self) -> anyhow::Result<()> {
+        if self.media.is_empty() {
+            return Ok(());
+        }
+
+        self.send(ClientMessageType::HaveMedia {
+            media: self.media.keys().copied().collect(),
+        })
+        .await?;
+        let missing = loop {
+            if let ServerMessageType::WantMedia { media } = self.next_message().await? {
+                break media;
+            }
+        };
+
+        // The server refuses more than `MAX_CONCURRENT_UPLOADS` at once, the next one begins when one completes
+        let total_size: u64 = missing.iter().map(|media| media.len).sum();
+        let mut pending = missing.into_iter();
+        let mut transfers = HashMap::new();
+        for media in pending.by_ref().take(MAX_CONCURRENT_UPLOADS) {
+            self.begin_transfer(&mut transfers, media).await?;
+        }
+
+        let mut uploaded = 0;
+        while !transfers.is_empty() {
+            print!(
+                "\rUploading media: {} / {} KiB",
+                uploaded / 1024,
+                total_size / 1024
+            );
+            std::io::stdout().flush()?;
+
+            let (transfer_id, chunks) = match self.next_message().await? {
+                ServerMessageType::ResumeTransfer {
+                    transfer_id,
+                    offset,
+                } => {
+                    let Some(transfer) = transfers.get_mut(&transfer_id) else {
+                        continue;
+                    };
+                    transfer.resume_from(offset)?;
+                    uploaded += offset;
+                    (transfer_id, next_chunks(transfer))
+                }
+                ServerMessageType::ChunkAck { transfer_id, index } => {
+                    let Some(transfer) = transfers.get_mut(&transfer_id) else {
+                        continue;
+                    };
+                    transfer.ack(index);
+                    (transfer_id, next_chunks(transfer))
+                }
+                ServerMessageType::TransferComplete { transfer_id } => {
+                    if transfers.remove(&transfer_id).is_some()
+                        && let Some(media) = pending.next()
+                    {
+                        self.begin_transfer(&mut transfers, media).await?;
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            for (index, bytes) in chunks {
+                uploaded += bytes.len() as u64;
+                self.send(ClientMessageType::TransferChunk {
+                    transfer_id,
+                    index,
+                    bytes,
+                })
+                .await?;
+            }
+        }
+
+        println!("\rUploaded {} KiB of media", total_size / 1024);
+        Ok(())
+    }
+
+    /// Announce the upload of `media`, whose chunks are sent once the server tells where to resume from.
+    async fn begin_transfer(
+        &mut self,
+        transfers: &mut HashMap<Uuid, OutgoingTransfer>,
+        media: MediaRef,
+    ) -> anyhow::Result<()> {
+        let transfer_id = media.transfer_id();
+        let data = self.media[&media].clone();
+        transfers.insert(
+            transfer_id,
+            OutgoingTransfer::with_digest(transfer_id, data, media.sha256),
+        );
+        self.send(ClientMessageType::BeginTransfer {
+            transfer_id,
+            total_size: media.len,
+            sha256: media.sha256,
+        })
+        .await
+    }
+
+    async fn send(&mut self, kind: ClientMessageType) -> anyhow::Result<()> {
+        let message = ClientMessage {
+            version: Version::CURRENT,
+            kind,
+        };
+        let bytes = self.codec.encode(&message)?;
+        self.socket.send(Message::Binary(bytes.into())).await?;
+        Ok(())
+    }
+
+    /// Wait for the next message from the server.
+    ///
+    /// Errors sent by the server are turned into an `Err`, and the progress of the last batch is kept track of.
+    async fn next_message(&mut self) -> anyhow::Result<ServerMessageType> {
+        loop {
+            let Some(message) = self.socket.next().await else {
+                anyhow::bail!("The server closed the connection");
+            };
+            let bytes = match message? {
+                Message::Binary(bytes) => bytes,
+                Message::Close(_) => anyhow::bail!("The server closed the connection"),
+                // Pings are answered by tungstenite itself
+                _ => continue,
+            };
+
+            let message: ServerMessage = self.codec.decode(&bytes)?;
+            match &message.kind {
+                ServerMessageType::Error { message } => anyhow::bail!("{message}"),
+                ServerMessageType::OverlaysFullAck => {
+                    if let Some(batch) = &mut self.batch {
+                        batch.downloaded = true;
+                    }
+                }
+                ServerMessageType::RasterizationFullAck => {
+                    if let Some(batch) = &mut self.batch {
+                        batch.rasterized = true;
+                    }
+                }
+                ServerMessageType::RasterizationFailed { reason } => {
+                    // Only the other splash-screens are waited on from now on
+                    println!(
+                        "\rSplash-screen {} could not rasterize the overlays: {reason}",
+                        message.sender.id
+                    );
+                }
+                ServerMessageType::PartyDisbanded { party_id } => {
+                    println!("Party {party_id} was disbanded");
+                    self.party_id = None;
+                    self.batch = None;
+                }
+                _ => {}
+            }
+
+            return Ok(message.kind);
+        }
+    }
+}
+
+/// Take every chunk of a transfer allowed by its window.
+fn next_chunks(transfer: &mut OutgoingTransfer) -> Vec<(u32, Vec<u8>)> {
+    let mut chunks = Vec::new();
+    while let Some((index, bytes)) = transfer.next_chunk() {
+        chunks.push((index, bytes.to_vec()));
+    }
+    chunks
+}
+
+fn checkbox(checked: bool) -> &'static str {
+    if checked { "[x]" } else { "[ ]" }
+}
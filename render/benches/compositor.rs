@@ -0,0 +1,68 @@
+use std::{hint::black_box, io::Cursor};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use friendlyfire_render::{Compositor, ImageOverlay, Lifetime};
+use image::{ImageFormat, Rgba, RgbaImage};
+
+/// 4K, the largest canvas we expect to render onto.
+const CANVAS: (u32, u32) = (3840, 2160);
+
+const FOREVER: Lifetime = Lifetime {
+    start_ms: 0,
+    end_ms: None,
+};
+
+/// Encode an image as a PNG, the way media reach the rasterizer.
+fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> Rgba<u8>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    RgbaImage::from_fn(width, height, pixel)
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+fn bench_render(c: &mut Criterion, name: &str, overlays: &[(Vec<u8>, i32, i32)]) {
+    let mut compositor = Compositor::new(CANVAS.0, CANVAS.1);
+    for (z_index, (bytes, left, top)) in overlays.iter().enumerate() {
+        let overlay = ImageOverlay::from_bytes(bytes, *left, *top, z_index as u32).unwrap();
+        compositor.add_overlay(Box::new(overlay), FOREVER);
+    }
+
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            // Static overlays only cause damage once, every iteration is a full redraw
+            compositor.invalidate();
+            black_box(compositor.render(black_box(0)).buffer.len())
+        })
+    });
+}
+
+fn compositor(c: &mut Criterion) {
+    let (width, height) = CANVAS;
+
+    bench_render(c, "render 4k empty", &[]);
+
+    let opaque = png(width, height, |x, y| Rgba([x as u8, y as u8, 0x80, 0xff]));
+    bench_render(c, "render 4k opaque", &[(opaque, 0, 0)]);
+
+    // Several translucent overlays on top of each other, partially off-screen
+    let translucent = png(1920, 1080, |x, y| Rgba([x as u8, y as u8, 0x80, 0x80]));
+    let stacked: Vec<_> = (0..4)
+        .map(|i| (translucent.clone(), i * 640 - 320, i * 360 - 180))
+        .collect();
+    bench_render(c, "render 4k 4 translucent", &stacked);
+
+    // Mostly transparent with antialiased edges, like text or stickers
+    let sparse = png(width, height, |x, y| {
+        let alpha = match (x / 64 + y / 64) % 4 {
+            0 => 0xff,
+            1 => (x % 256) as u8,
+            _ => 0,
+        };
+        Rgba([0xff, 0xff, 0xff, alpha])
+    });
+    bench_render(c, "render 4k sparse", &[(sparse, 0, 0)]);
+}
+
+criterion_group!(benches, compositor);
+criterion_main!(benches);
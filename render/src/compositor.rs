@@ -0,0 +1,369 @@
+use crate::{frame::Frame, overlay::Overlay, rect::Rect};
+
+/// Time span during which an `Overlay` is shown.
+///
+/// Timestamps are in milliseconds, on the same timeline as the `timestamp_ms` given to `Compositor::render`.
+#[derive(Clone, Copy, Debug)]
+pub struct Lifetime {
+    /// When the overlay appears.
+    pub start_ms: u128,
+    /// When the overlay disappears, `None` to keep it forever.
+    pub end_ms: Option<u128>,
+}
+
+impl Lifetime {
+    fn is_visible(&self, timestamp_ms: u128) -> bool {
+        self.start_ms <= timestamp_ms && !self.is_over(timestamp_ms)
+    }
+
+    fn is_over(&self, timestamp_ms: u128) -> bool {
+        self.end_ms.is_some_and(|end_ms| end_ms <= timestamp_ms)
+    }
+
+    /// Time in milliseconds until the overlay appears or disappears, if any of those are still to come.
+    fn time_to_next_transition_ms(&self, timestamp_ms: u128) -> Option<u128> {
+        if timestamp_ms < self.start_ms {
+            return Some(self.start_ms - timestamp_ms);
+        }
+        self.end_ms
+            .filter(|end_ms| timestamp_ms < *end_ms)
+            .map(|end_ms| end_ms - timestamp_ms)
+    }
+}
+
+/// An `Overlay` along with the time span during which it is shown.
+struct ScheduledOverlay {
+    overlay: Box<dyn Overlay>,
+    lifetime: Lifetime,
+    /// Local timestamp the overlay was last drawn at, `None` if it is not on the canvas.
+    drawn_at_ms: Option<u128>,
+}
+
+impl ScheduledOverlay {
+    /// Areas of the canvas this overlay changes when going from what was drawn last to `timestamp_ms`.
+    fn damage(&self, timestamp_ms: u128) -> Vec<Rect> {
+        let visible_ms = self
+            .lifetime
+            .is_visible(timestamp_ms)
+            .then(|| timestamp_ms - self.lifetime.start_ms);
+
+        match (self.drawn_at_ms, visible_ms) {
+            (None, None) => Vec::new(),
+            (Some(drawn_at_ms), Some(local_timestamp_ms)) => {
+                self.overlay.damage(drawn_at_ms, local_timestamp_ms)
+            }
+            // Appears or disappears
+            (Some(local_timestamp_ms), None) | (None, Some(local_timestamp_ms)) => {
+                vec![self.overlay.bounds(local_timestamp_ms)]
+            }
+        }
+    }
+}
+
+/// Central composition engine responsible for producing the "final `Frame`" from a bunch of `Overlay`.
+///
+/// It's job is to order the multiple `Overlay` given to him by their `Overlay.z_index()` and draw them in order.
+/// Each `Overlay` is only drawn during its `Lifetime`, and dropped once it is over.
+///
+/// Only the regions of the canvas that changed since the last render are redrawn, see `Compositor::damage`.
+pub struct Compositor {
+    /// The main output frame (canvas).
+    ///
+    /// The origin point of the canvas is the same as `Overlay`, i.e. it follow the CSSOM standard.
+    /// See https://developer.mozilla.org/en-US/docs/Web/API/CSSOM_view_API/Coordinate_systems
+    pub canvas: Frame,
+    /// Registered overlays (static or animated).
+    overlays: Vec<ScheduledOverlay>,
+    /// Regions of the canvas redrawn by the last render, they do not overlap each other.
+    damage: Vec<Rect>,
+    /// Whether the whole canvas must be redrawn on the next render, e.g. on the first one.
+    is_stale: bool,
+}
+
+impl Compositor {
+    /// Create a new compositor with a fixed canvas size.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            canvas: Frame::new(0, 0, width, height, 0),
+            overlays: Vec::new(),
+            damage: Vec::new(),
+            is_stale: true,
+        }
+    }
+
+    /// Register a new overlay that will be composited onto the `self.canvas` during its `lifetime`.
+    pub fn add_overlay(&mut self, overlay: Box<dyn Overlay>, lifetime: Lifetime) {
+        self.overlays.push(ScheduledOverlay {
+            overlay,
+            lifetime,
+            drawn_at_ms: None,
+        });
+    }
+
+    /// Width and height of the canvas, in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.canvas.width, self.canvas.height)
+    }
+
+    /// Whether there is no overlay left to show, now or later.
+    pub fn is_empty(&self) -> bool {
+        self.overlays.is_empty()
+    }
+
+    /// Redraw the whole canvas on the next `Compositor::render`, e.g. when it was altered from outside.
+    pub fn invalidate(&mut self) {
+        self.is_stale = true;
+    }
+
+    /// Regions of the canvas that changed during the last `Compositor::render`, clipped to the canvas.
+    ///
+    /// Empty when nothing changed, so that presenting the canvas again can be skipped altogether.
+    pub fn damage(&self) -> &[Rect] {
+        &self.damage
+    }
+
+    /// Render the `self.canvas` for the given timestamp.
+    ///
+    /// Overlays are given a timestamp relative to the moment they appeared,
+    /// so that animations always start from their first frame.
+    ///
+    /// Only the damaged regions are cleared and redrawn, the rest of the canvas is kept from the last render.
+    pub fn render(&mut self, timestamp_ms: u128) -> &Frame {
+        let canvas_area = Rect::new(0, 0, self.canvas.width, self.canvas.height);
+
+        let damage: Vec<Rect> = if std::mem::take(&mut self.is_stale) {
+            vec![canvas_area]
+        } else {
+            self.overlays
+                .iter()
+                .flat_map(|o| o.damage(timestamp_ms))
+                .filter_map(|rect| rect.intersection(&canvas_area))
+                .collect()
+        };
+        self.damage = merge_overlapping(damage);
+
+        self.overlays.retain(|o| !o.lifetime.is_over(timestamp_ms));
+        self.overlays.sort_by_key(|o| o.overlay.z_index());
+
+        for rect in &self.damage {
+            self.canvas.clear_rect(*rect);
+        }
+        for scheduled in &mut self.overlays {
+            if !scheduled.lifetime.is_visible(timestamp_ms) {
+                scheduled.drawn_at_ms = None;
+                continue;
+            }
+
+            let local_timestamp_ms = timestamp_ms - scheduled.lifetime.start_ms;
+            let bounds = scheduled.overlay.bounds(local_timestamp_ms);
+            for rect in &self.damage {
+                if let Some(clip) = rect.intersection(&bounds) {
+                    scheduled
+                        .overlay
+                        .draw(&mut self.canvas, clip, local_timestamp_ms);
+                }
+            }
+            scheduled.drawn_at_ms = Some(local_timestamp_ms);
+        }
+
+        &self.canvas
+    }
+
+    /// Return the earliest time any `Overlay` wants its next `Frame` to be shown,
+    /// or any `Overlay` appears or disappears.
+    ///
+    /// Returning `None` indicates that no overlay require time-based updates (static content).
+    pub fn time_until_next_frame_ms(&self, timestamp_ms: u128) -> Option<u128> {
+        self.overlays
+            .iter()
+            .flat_map(|o| {
+                let next_frame_ms = o
+                    .lifetime
+                    .is_visible(timestamp_ms)
+                    .then(|| {
+                        let local_timestamp_ms = timestamp_ms - o.lifetime.start_ms;
+                        o.overlay.time_to_next_frame_ms(local_timestamp_ms)
+                    })
+                    .flatten();
+
+                [
+                    next_frame_ms,
+                    o.lifetime.time_to_next_transition_ms(timestamp_ms),
+                ]
+            })
+            .flatten()
+            .min()
+    }
+}
+
+/// Merge overlapping rectangles into their bounding rectangle, until none of them overlap.
+///
+/// Every pixel is then redrawn once, and backends get a short list of regions to update.
+fn merge_overlapping(mut rects: Vec<Rect>) -> Vec<Rect> {
+    let mut merged: Vec<Rect> = Vec::with_capacity(rects.len());
+
+    while let Some(mut rect) = rects.pop() {
+        // A merged rectangle grows, and may now overlap rectangles it was checked against
+        while let Some(i) = merged
+            .iter()
+            .position(|other| other.intersection(&rect).is_some())
+        {
+            rect = rect.bounding(&merged.swap_remove(i));
+        }
+        merged.push(rect);
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An overlay moving one pixel to the right every 10ms, and flipping its color every 20ms.
+    struct Sliding {
+        z_index: u32,
+        size: u32,
+    }
+
+    impl Sliding {
+        fn frame(&self, timestamp_ms: u128) -> Frame {
+            let color = if (timestamp_ms / 20).is_multiple_of(2) {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 128, 128]
+            };
+            let pixels = color.repeat((self.size * self.size) as usize);
+            let left = (timestamp_ms / 10) as i32 - 2;
+            Frame::from_bytes(left, self.z_index as i32, self.size, self.size, &pixels, 0)
+        }
+    }
+
+    impl Overlay for Sliding {
+        fn z_index(&self) -> u32 {
+            self.z_index
+        }
+
+        fn draw(&self, target: &mut Frame, clip: Rect, timestamp_ms: u128) {
+            let frame = self.frame(timestamp_ms);
+            target.blit_clipped(
+                clip,
+                frame.offset_left,
+                frame.offset_top,
+                frame.width,
+                frame.height,
+                &frame.buffer,
+            );
+        }
+
+        fn bounds(&self, timestamp_ms: u128) -> Rect {
+            self.frame(timestamp_ms).bounds()
+        }
+
+        fn damage(&self, previous_ms: u128, timestamp_ms: u128) -> Vec<Rect> {
+            vec![self.bounds(previous_ms), self.bounds(timestamp_ms)]
+        }
+
+        fn time_to_next_frame_ms(&self, _timestamp_ms: u128) -> Option<u128> {
+            Some(10)
+        }
+    }
+
+    /// An overlay that never changes.
+    struct Still;
+
+    impl Overlay for Still {
+        fn z_index(&self) -> u32 {
+            0
+        }
+
+        fn draw(&self, target: &mut Frame, clip: Rect, _timestamp_ms: u128) {
+            target.blit_clipped(clip, 0, 0, 1, 1, &[255; 4]);
+        }
+
+        fn bounds(&self, _timestamp_ms: u128) -> Rect {
+            Rect::new(0, 0, 1, 1)
+        }
+
+        fn time_to_next_frame_ms(&self, _timestamp_ms: u128) -> Option<u128> {
+            None
+        }
+    }
+
+    fn compositor() -> Compositor {
+        let mut compositor = Compositor::new(8, 6);
+        for (z_index, start_ms, end_ms) in [(0, 0, None), (1, 15, Some(70)), (2, 30, Some(50))] {
+            let overlay = Sliding { z_index, size: 3 };
+            compositor.add_overlay(Box::new(overlay), Lifetime { start_ms, end_ms });
+        }
+        compositor
+    }
+
+    #[test]
+    fn partial_redraw_matches_full_redraw() {
+        let mut partial = compositor();
+        for timestamp_ms in (0..120).step_by(5) {
+            partial.render(timestamp_ms);
+
+            let mut full = compositor();
+            full.render(timestamp_ms);
+            assert_eq!(
+                partial.canvas.buffer, full.canvas.buffer,
+                "at {timestamp_ms}ms"
+            );
+        }
+    }
+
+    #[test]
+    fn damage_is_clipped_and_disjoint() {
+        let mut compositor = compositor();
+        let canvas_area = Rect::new(0, 0, 8, 6);
+
+        compositor.render(0);
+        assert_eq!(compositor.damage(), [canvas_area]);
+
+        for timestamp_ms in (5..120).step_by(5) {
+            compositor.render(timestamp_ms);
+            let damage = compositor.damage();
+            for (i, rect) in damage.iter().enumerate() {
+                assert_eq!(rect.intersection(&canvas_area), Some(*rect));
+                for other in &damage[i + 1..] {
+                    assert_eq!(rect.intersection(other), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_damage_without_changes() {
+        let mut compositor = Compositor::new(8, 6);
+        compositor.render(0);
+        compositor.render(10);
+        assert!(compositor.damage().is_empty());
+    }
+
+    #[test]
+    fn still_overlays_wake_up_for_their_transitions() {
+        let mut compositor = Compositor::new(8, 6);
+        let lifetime = Lifetime {
+            start_ms: 100,
+            end_ms: Some(250),
+        };
+        compositor.add_overlay(Box::new(Still), lifetime);
+
+        // Nothing is animating, yet the overlay is still to appear and then disappear
+        assert_eq!(compositor.time_until_next_frame_ms(0), Some(100));
+        assert_eq!(compositor.time_until_next_frame_ms(100), Some(150));
+        assert_eq!(compositor.time_until_next_frame_ms(240), Some(10));
+        assert_eq!(compositor.time_until_next_frame_ms(250), None);
+
+        compositor.add_overlay(
+            Box::new(Still),
+            Lifetime {
+                start_ms: 0,
+                end_ms: None,
+            },
+        );
+        assert_eq!(compositor.time_until_next_frame_ms(300), None);
+    }
+}
@@ -0,0 +1,427 @@
+use ff::{TextBackground, TextEffects};
+
+use crate::{
+    frame::{Frame, mul_div255},
+    rect::Rect,
+    transform::MAX_DIMENSION,
+};
+
+/// Largest stroke width and blur radius, in pixels.
+/// The cost of both grows with the square of their size, and nobody needs a thicker outline anyway.
+const MAX_EFFECT_SIZE: f32 = 64.0;
+
+/// Draw `effects` behind some rasterized text, returning a frame that covers the text and its effects.
+///
+/// `glyphs` and `lines` (the area covered by the lines of text) share the same coordinate system,
+/// which the offsets of the returned frame are in too.
+pub(crate) fn decorate(glyphs: Frame, lines: Rect, effects: &TextEffects) -> anyhow::Result<Frame> {
+    // Text without glyphs (e.g. only line breaks) has nothing to outline nor to cast a shadow
+    let effects = &if glyphs.bounds().is_empty() {
+        TextEffects {
+            background: effects.background,
+            ..TextEffects::default()
+        }
+    } else {
+        *effects
+    };
+    if *effects == TextEffects::default() {
+        return Ok(glyphs);
+    }
+
+    let stroke_width = effects.stroke.map_or(0.0, |stroke| stroke.width);
+    let blur_radius = effects.shadow.map_or(0.0, |shadow| shadow.blur_radius);
+    for (name, size) in [("stroke width", stroke_width), ("blur radius", blur_radius)] {
+        anyhow::ensure!(
+            size.is_finite() && (0.0..=MAX_EFFECT_SIZE).contains(&size),
+            "the {name} must be between 0 and {MAX_EFFECT_SIZE} pixels, got {size}"
+        );
+    }
+    if let Some(background) = effects.background {
+        anyhow::ensure!(
+            background.corner_radius.is_finite() && background.corner_radius >= 0.0,
+            "the corner radius must be a positive number"
+        );
+    }
+
+    // Everything that gets drawn, in the coordinates of `glyphs`
+    let outline = glyphs.bounds().inflate(dilation_reach(stroke_width));
+    let mut area = outline;
+    // Masks are computed on a canvas that also covers the shadow before it is moved
+    let mut canvas = outline;
+    if let Some(shadow) = effects.shadow {
+        let blurred = outline.inflate(blur_reach(blur_radius));
+        area = area.bounding(&blurred.translate(shadow.offset_x, shadow.offset_y));
+        canvas = canvas.bounding(&blurred);
+    }
+    if let Some(background) = effects.background {
+        area = area.bounding(&lines.inflate(background.padding));
+    }
+    canvas = canvas.bounding(&area);
+    anyhow::ensure!(
+        canvas.width <= MAX_DIMENSION && canvas.height <= MAX_DIMENSION,
+        "the text and its effects cannot be larger than {MAX_DIMENSION}x{MAX_DIMENSION} pixels, got {}x{}",
+        canvas.width,
+        canvas.height
+    );
+
+    let (width, height) = (canvas.width as usize, canvas.height as usize);
+    // Where the canvas is drawn onto the frame
+    let (canvas_x, canvas_y) = (canvas.left - area.left, canvas.top - area.top);
+    let mut frame = Frame::new(area.left, area.top, area.width, area.height, 0);
+    let mut draw = |x: i32, y: i32, mask: &[u8], color: [u8; 4]| {
+        let pixels = colorize(mask, color);
+        frame.blit(
+            canvas_x + x,
+            canvas_y + y,
+            canvas.width,
+            canvas.height,
+            &pixels,
+        );
+    };
+
+    // Coverage of the glyphs, grown by the stroke, which is what casts the shadow
+    let (glyphs_x, glyphs_y) = (
+        glyphs.offset_left - canvas.left,
+        glyphs.offset_top - canvas.top,
+    );
+    let mut silhouette = vec![0; width * height];
+    if !glyphs.bounds().is_empty() {
+        for (row, src) in glyphs
+            .buffer
+            .chunks_exact(glyphs.width as usize * 4)
+            .enumerate()
+        {
+            let start = (glyphs_y as usize + row) * width + glyphs_x as usize;
+            for (dst, px) in silhouette[start..].iter_mut().zip(src.chunks_exact(4)) {
+                *dst = px[3];
+            }
+        }
+    }
+    if stroke_width > 0.0 {
+        silhouette = dilate(&silhouette, width, height, stroke_width);
+    }
+
+    if let Some(background) = effects.background {
+        let rect = lines
+            .inflate(background.padding)
+            .translate(-canvas.left, -canvas.top);
+        draw(
+            0,
+            0,
+            &rounded_rect(width, height, rect, &background),
+            background.color,
+        );
+    }
+    if let Some(shadow) = effects.shadow {
+        let mask = if blur_radius > 0.0 {
+            blur(&silhouette, width, height, blur_radius)
+        } else {
+            silhouette.clone()
+        };
+        draw(shadow.offset_x, shadow.offset_y, &mask, shadow.color);
+    }
+    if let Some(stroke) = effects.stroke {
+        draw(0, 0, &silhouette, stroke.color);
+    }
+    frame.blit(
+        canvas_x + glyphs_x,
+        canvas_y + glyphs_y,
+        glyphs.width,
+        glyphs.height,
+        &glyphs.buffer,
+    );
+
+    Ok(frame)
+}
+
+/// How far `dilate` reaches around the mask.
+fn dilation_reach(radius: f32) -> u32 {
+    // Pixels are covered up to `radius` from the edges of the pixels of the mask, not from their center
+    (radius + 1.0).ceil() as u32 - 1
+}
+
+/// How far `blur` reaches around the mask.
+fn blur_reach(radius: f32) -> u32 {
+    // Three standard deviations hold more than 99% of the gaussian
+    (1.5 * radius).ceil() as u32
+}
+
+/// Grow a coverage mask by `radius` pixels in every direction, with anti-aliased edges.
+fn dilate(mask: &[u8], width: usize, height: usize, radius: f32) -> Vec<u8> {
+    // Squared distance from every pixel to the closest pixel that is mostly covered
+    let mut distances: Vec<f32> = mask
+        .iter()
+        .map(|&coverage| if coverage >= 128 { 0.0 } else { FAR })
+        .collect();
+    let mut line = Vec::new();
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| distances[y * width + x]));
+        for (y, distance) in distance_transform(&line).into_iter().enumerate() {
+            distances[y * width + x] = distance;
+        }
+    }
+    for row in distances.chunks_exact_mut(width) {
+        let transformed = distance_transform(row);
+        row.copy_from_slice(&transformed);
+    }
+
+    mask.iter()
+        .zip(distances)
+        .map(|(&coverage, distance)| {
+            // Pixels are covered up to `radius` from the edges of the covered pixels, not from their center
+            let grown = (radius + 1.0 - distance.sqrt()).clamp(0.0, 1.0);
+            coverage.max((grown * 255.0).round() as u8)
+        })
+        .collect()
+}
+
+/// Squared distance standing for "no covered pixel anywhere", but that still adds up without overflowing.
+const FAR: f32 = 1e20;
+
+/// One dimensional squared euclidean distance transform, from "Distance Transforms of Sampled Functions"
+/// (Felzenszwalb and Huttenlocher). Every value becomes `min(f(q) + (p - q)²)` over every `q`, in linear time.
+fn distance_transform(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut transformed = vec![0.0; n];
+    if n == 0 {
+        return transformed;
+    }
+
+    // Parabolas of the lower envelope, and the boundaries between them
+    let mut vertices = vec![0; n];
+    let mut boundaries = vec![0.0; n + 1];
+    let mut k = 0;
+    boundaries[0] = f32::NEG_INFINITY;
+    boundaries[1] = f32::INFINITY;
+    let parabola = |q: usize| f[q] + (q * q) as f32;
+
+    for q in 1..n {
+        let mut s;
+        loop {
+            let v = vertices[k];
+            s = (parabola(q) - parabola(v)) / (2 * (q - v)) as f32;
+            if s > boundaries[k] {
+                break;
+            }
+            k -= 1;
+        }
+        k += 1;
+        vertices[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (p, value) in transformed.iter_mut().enumerate() {
+        while boundaries[k + 1] < p as f32 {
+            k += 1;
+        }
+        let v = vertices[k];
+        *value = (p.abs_diff(v) * p.abs_diff(v)) as f32 + f[v];
+    }
+    transformed
+}
+
+/// Gaussian blur of a coverage mask, `radius` being twice the standard deviation (as in CSS).
+fn blur(mask: &[u8], width: usize, height: usize, radius: f32) -> Vec<u8> {
+    let sigma = radius / 2.0;
+    let reach = blur_reach(radius) as i64;
+    let kernel: Vec<f32> = (-reach..=reach)
+        .map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+
+    // The gaussian is separable, blurring the rows then the columns is the same as blurring in 2D
+    let pass = |src: &[f32], step: usize, count: usize, length: usize| {
+        let mut dst = vec![0.0; src.len()];
+        for line in 0..count {
+            let start = if step == 1 { line * length } else { line };
+            for i in 0..length as i64 {
+                let mut sum = 0.0;
+                for (k, weight) in kernel.iter().enumerate() {
+                    let j = i + k as i64 - reach;
+                    if (0..length as i64).contains(&j) {
+                        sum += src[start + j as usize * step] * weight;
+                    }
+                }
+                dst[start + i as usize * step] = sum / total;
+            }
+        }
+        dst
+    };
+    let mask: Vec<f32> = mask.iter().map(|&value| value as f32).collect();
+    let rows = pass(&mask, 1, height, width);
+    let columns = pass(&rows, width, width, height);
+
+    columns
+        .into_iter()
+        .map(|value| value.round().clamp(0.0, 255.0) as u8)
+        .collect()
+}
+
+/// Coverage mask of `rect` with rounded corners, with anti-aliased edges.
+fn rounded_rect(width: usize, height: usize, rect: Rect, background: &TextBackground) -> Vec<u8> {
+    let half_width = rect.width as f32 / 2.0;
+    let half_height = rect.height as f32 / 2.0;
+    let radius = background.corner_radius.min(half_width).min(half_height);
+    let center_x = rect.left as f32 + half_width;
+    let center_y = rect.top as f32 + half_height;
+
+    let mut mask = vec![0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            // Signed distance from the center of the pixel to the edge of the rectangle, negative inside
+            let qx = (x as f32 + 0.5 - center_x).abs() - (half_width - radius);
+            let qy = (y as f32 + 0.5 - center_y).abs() - (half_height - radius);
+            let outside = qx.max(0.0).hypot(qy.max(0.0));
+            let distance = outside + qx.max(qy).min(0.0) - radius;
+
+            mask[y * width + x] = ((0.5 - distance).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    mask
+}
+
+/// Premultiplied RGBA pixels of a straight RGBA `color`, covering what `mask` covers.
+pub(crate) fn colorize(mask: &[u8], color: [u8; 4]) -> Vec<u8> {
+    let [r, g, b, a] = color.map(u32::from);
+    let mut pixels = Vec::with_capacity(mask.len() * 4);
+    for &coverage in mask {
+        let alpha = mul_div255(a, coverage as u32);
+        pixels.extend_from_slice(&[
+            mul_div255(r, alpha) as u8,
+            mul_div255(g, alpha) as u8,
+            mul_div255(b, alpha) as u8,
+            alpha as u8,
+        ]);
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::{TextShadow, TextStroke};
+
+    use super::*;
+
+    /// A single opaque white pixel at (`left`, `top`).
+    fn dot(left: i32, top: i32) -> Frame {
+        Frame::from_bytes(left, top, 1, 1, &[255; 4], 0)
+    }
+
+    fn alpha_at(frame: &Frame, x: i32, y: i32) -> u8 {
+        let (x, y) = (
+            (x - frame.offset_left) as usize,
+            (y - frame.offset_top) as usize,
+        );
+        frame.buffer[(y * frame.width as usize + x) * 4 + 3]
+    }
+
+    #[test]
+    fn no_effect_keeps_the_glyphs() {
+        let frame = decorate(dot(3, 4), Rect::new(0, 0, 10, 10), &TextEffects::default()).unwrap();
+        assert_eq!(frame.bounds(), Rect::new(3, 4, 1, 1));
+    }
+
+    #[test]
+    fn empty_text_keeps_its_background() {
+        let effects = TextEffects {
+            stroke: Some(TextStroke {
+                width: 2.0,
+                color: [0, 0, 0, 255],
+            }),
+            shadow: Some(TextShadow {
+                offset_x: 2,
+                offset_y: 2,
+                blur_radius: 2.0,
+                color: [0, 0, 0, 255],
+            }),
+            background: Some(TextBackground {
+                padding: 4,
+                corner_radius: 0.0,
+                color: [0, 0, 0, 255],
+            }),
+        };
+        let glyphs = Frame::new(0, 0, 0, 0, 0);
+        let frame = decorate(glyphs, Rect::new(0, 0, 0, 10), &effects).unwrap();
+
+        assert_eq!(frame.bounds(), Rect::new(-4, -4, 8, 18));
+        assert_eq!(alpha_at(&frame, 0, 0), 255);
+    }
+
+    #[test]
+    fn stroke_surrounds_the_glyphs() {
+        let effects = TextEffects {
+            stroke: Some(TextStroke {
+                width: 2.0,
+                color: [0, 0, 0, 255],
+            }),
+            ..TextEffects::default()
+        };
+        let frame = decorate(dot(10, 10), Rect::new(0, 0, 1, 1), &effects).unwrap();
+
+        assert_eq!(frame.bounds(), Rect::new(8, 8, 5, 5));
+        // The glyph stays on top of its outline
+        assert_eq!(&frame.buffer[(2 * 5 + 2) * 4..][..4], &[255; 4]);
+        assert_eq!(alpha_at(&frame, 12, 10), 255);
+        assert_eq!(alpha_at(&frame, 10, 8), 255);
+        // Anti-aliased corners
+        assert!((1..255).contains(&alpha_at(&frame, 12, 12)));
+    }
+
+    #[test]
+    fn shadow_is_offset_and_blurred() {
+        let effects = TextEffects {
+            shadow: Some(TextShadow {
+                offset_x: 5,
+                offset_y: -5,
+                blur_radius: 2.0,
+                color: [0, 0, 0, 255],
+            }),
+            ..TextEffects::default()
+        };
+        let frame = decorate(dot(0, 0), Rect::new(0, 0, 1, 1), &effects).unwrap();
+
+        assert_eq!(frame.bounds(), Rect::new(0, -8, 9, 9));
+        let center = alpha_at(&frame, 5, -5);
+        assert_eq!(alpha_at(&frame, 4, -5), alpha_at(&frame, 6, -5));
+        assert!(center > alpha_at(&frame, 6, -5));
+        assert!(alpha_at(&frame, 6, -5) > alpha_at(&frame, 7, -5));
+        // The blurred shadow of a single pixel is not opaque anymore
+        assert!(center < 255);
+    }
+
+    #[test]
+    fn background_covers_the_padded_lines() {
+        let effects = TextEffects {
+            background: Some(TextBackground {
+                padding: 4,
+                corner_radius: 3.0,
+                color: [0, 0, 0, 128],
+            }),
+            ..TextEffects::default()
+        };
+        let frame = decorate(dot(5, 5), Rect::new(0, 0, 20, 10), &effects).unwrap();
+
+        assert_eq!(frame.bounds(), Rect::new(-4, -4, 28, 18));
+        assert_eq!(alpha_at(&frame, 0, 0), 128);
+        assert_eq!(alpha_at(&frame, 5, 5), 255);
+        // Rounded corners
+        assert_eq!(alpha_at(&frame, -4, -4), 0);
+        assert_eq!(alpha_at(&frame, 23, 13), 0);
+        assert_eq!(alpha_at(&frame, 10, -4), 128);
+    }
+
+    #[test]
+    fn oversized_effects_are_rejected() {
+        let effects = TextEffects {
+            stroke: Some(TextStroke {
+                width: f32::NAN,
+                color: [0; 4],
+            }),
+            ..TextEffects::default()
+        };
+        assert!(decorate(dot(0, 0), Rect::new(0, 0, 1, 1), &effects).is_err());
+    }
+}
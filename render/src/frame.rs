@@ -0,0 +1,404 @@
+use crate::rect::Rect;
+
+#[derive(Clone)]
+pub struct Frame {
+    /// Horizontal offset from the left edge. We are using the top-left corner as the origin as seen in CSSOM.
+    /// See https://developer.mozilla.org/en-US/docs/Web/API/CSSOM_view_API/Coordinate_systems
+    ///
+    /// The offset can be negative
+    pub offset_left: i32,
+
+    /// Vertical offset from the top edge. We are using the top-left corner as the origin as seen in CSSOM.
+    /// See https://developer.mozilla.org/en-US/docs/Web/API/CSSOM_view_API/Coordinate_systems
+    ///
+    /// The offset can be negative
+    pub offset_top: i32,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Premultiplied RGBA8, so that blending does not need any division.
+    ///
+    /// Conversion to other formats happens in the `Rendering` structs/traits specific to any `Window`
+    /// Win32 : BGRA
+    /// X11 : ARGB
+    /// Wayland : wl_shm ARGB
+    /// Headless : straight RGBA, see `Frame::to_straight_rgba`
+    pub buffer: Vec<u8>,
+
+    /// Frame duration in milliseconds.
+    /// 0 = treat as "static".
+    // u128 was chosen for consistency reasons with the `image` crate
+    pub delay_ms: u128,
+}
+
+impl Frame {
+    pub fn new(offset_left: i32, offset_top: i32, width: u32, height: u32, delay_ms: u128) -> Self {
+        Self {
+            offset_left,
+            offset_top,
+            width,
+            height,
+            buffer: vec![0; (width * height * 4) as usize],
+            delay_ms,
+        }
+    }
+
+    /// Create a frame from straight (non-premultiplied) RGBA bytes, as given by image decoders.
+    pub fn from_bytes(
+        offset_left: i32,
+        offset_top: i32,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        delay_ms: u128,
+    ) -> Self {
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        Self {
+            offset_left,
+            offset_top,
+            width,
+            height,
+            buffer: pixels
+                .chunks_exact(4)
+                .flat_map(|px| {
+                    let a = px[3] as u32;
+                    [
+                        mul_div255(px[0] as u32, a) as u8,
+                        mul_div255(px[1] as u32, a) as u8,
+                        mul_div255(px[2] as u32, a) as u8,
+                        px[3],
+                    ]
+                })
+                .collect(),
+            delay_ms,
+        }
+    }
+
+    /// Straight (non-premultiplied) RGBA bytes of the frame, e.g. to encode it as a PNG.
+    pub fn to_straight_rgba(&self) -> Vec<u8> {
+        self.buffer
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let a = px[3] as u32;
+                if a == 0 {
+                    return [0; 4];
+                }
+                let unpremultiply = |c: u8| ((c as u32 * 255 + a / 2) / a).min(255) as u8;
+                [
+                    unpremultiply(px[0]),
+                    unpremultiply(px[1]),
+                    unpremultiply(px[2]),
+                    px[3],
+                ]
+            })
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.fill(0);
+    }
+
+    /// Area covered by the frame once drawn, i.e. at its offsets.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.offset_left, self.offset_top, self.width, self.height)
+    }
+
+    /// Area of the frame buffer itself, i.e. at the origin.
+    fn area(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Clear the part of the frame buffer inside `rect` to full transparency.
+    pub fn clear_rect(&mut self, rect: Rect) {
+        let Some(rect) = rect.intersection(&self.area()) else {
+            return;
+        };
+
+        let frame_width = self.width as usize;
+        for row in rect.top as usize..rect.top as usize + rect.height as usize {
+            let start = (row * frame_width + rect.left as usize) * 4;
+            self.buffer[start..start + rect.width as usize * 4].fill(0);
+        }
+    }
+
+    /// Copy a premultiplied RGBA image onto the frame buffer with no resizing, its top-left corner at (`dst_x`, `dst_y`).
+    /// `src_pixels` must be exactly (src_width * src_height * 4) bytes.
+    ///
+    /// The destination can be negative or past the edges of the frame, only the part of the image
+    /// overlapping the frame is drawn.
+    pub fn blit(
+        &mut self,
+        dst_x: i32,
+        dst_y: i32,
+        src_width: u32,
+        src_height: u32,
+        src_pixels: &[u8],
+    ) {
+        self.blit_clipped(self.area(), dst_x, dst_y, src_width, src_height, src_pixels);
+    }
+
+    /// Same as `Frame::blit`, but only the pixels inside `clip` are drawn.
+    // TODO: This really should be GPU-accelerated
+    pub fn blit_clipped(
+        &mut self,
+        clip: Rect,
+        dst_x: i32,
+        dst_y: i32,
+        src_width: u32,
+        src_height: u32,
+        src_pixels: &[u8],
+    ) {
+        assert_eq!(src_pixels.len(), (src_width * src_height * 4) as usize);
+
+        // Visible rectangle in frame coordinates
+        let Some(visible) = Rect::new(dst_x, dst_y, src_width, src_height)
+            .intersection(&clip)
+            .and_then(|rect| rect.intersection(&self.area()))
+        else {
+            return;
+        };
+        let (left, top) = (visible.left as i64, visible.top as i64);
+        let (right, bottom) = (left + visible.width as i64, top + visible.height as i64);
+
+        // Same rectangle in image coordinates
+        let src_left = (left - dst_x as i64) as usize;
+        let src_top = (top - dst_y as i64) as usize;
+        let visible_width = (right - left) as usize;
+
+        let frame_width = self.width as usize;
+        let src_width = src_width as usize;
+
+        for row in 0..(bottom - top) as usize {
+            let dst_start = ((top as usize + row) * frame_width + left as usize) * 4;
+            let src_start = ((src_top + row) * src_width + src_left) * 4;
+
+            let dst_row = &mut self.buffer[dst_start..dst_start + visible_width * 4];
+            let src_row = &src_pixels[src_start..src_start + visible_width * 4];
+            blend_row(dst_row, src_row);
+        }
+    }
+}
+
+/// Blend a row of premultiplied RGBA pixels over another one ("source over" operator).
+///
+/// Rows that are fully opaque or fully transparent, the bulk of most images, skip the blending altogether.
+fn blend_row(dst: &mut [u8], src: &[u8]) {
+    let (src_pixels, _) = src.as_chunks::<4>();
+    if src_pixels.iter().all(|px| px[3] == u8::MAX) {
+        dst.copy_from_slice(src);
+        return;
+    }
+    if src_pixels.iter().all(|px| px[3] == 0) {
+        return;
+    }
+
+    // Branchless so that the compiler can vectorize it, fully opaque or transparent pixels blend correctly anyway
+    let (dst_pixels, _) = dst.as_chunks_mut::<4>();
+    for (dst, src) in dst_pixels.iter_mut().zip(src_pixels) {
+        let src = u32::from_le_bytes(*src);
+        let old = u32::from_le_bytes(*dst);
+        // With premultiplied colors, "over" is `src + dst * (1 - src_a)` on every channel,
+        // which cannot overflow a channel as long as colors do not exceed their alpha
+        let out = src + scale_pixel(old, 255 - (src >> 24));
+        *dst = out.to_le_bytes();
+    }
+}
+
+/// Multiply every channel of a little-endian RGBA pixel by `factor / 255`, rounded to the nearest.
+///
+/// Channels are processed two at a time, each in 16 bits of a `u32` (R and B, then G and A).
+fn scale_pixel(pixel: u32, factor: u32) -> u32 {
+    const LOW_BYTES: u32 = 0x00ff00ff;
+    const HALF: u32 = 0x00800080;
+
+    let rb = (pixel & LOW_BYTES) * factor + HALF;
+    let ga = ((pixel >> 8) & LOW_BYTES) * factor + HALF;
+
+    let rb = ((rb + ((rb >> 8) & LOW_BYTES)) >> 8) & LOW_BYTES;
+    let ga = (ga + ((ga >> 8) & LOW_BYTES)) & !LOW_BYTES;
+    rb | ga
+}
+
+/// `a * b / 255` rounded to the nearest, for `a` and `b` in `0..=255`.
+pub(crate) fn mul_div255(a: u32, b: u32) -> u32 {
+    let x = a * b + 128;
+    (x + (x >> 8)) >> 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opaque image whose every pixel is unique, so that any misplaced pixel is caught.
+    fn numbered_image(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i % 251) as u8 + 1, (i / 251) as u8, 0x80, 0xff])
+            .collect()
+    }
+
+    /// Blit an opaque image onto a transparent 4x3 frame, and compare with a naive per-pixel copy.
+    fn assert_blit(dst_x: i32, dst_y: i32, src_width: u32, src_height: u32) {
+        let src = numbered_image(src_width, src_height);
+        let mut frame = Frame::new(0, 0, 4, 3, 0);
+        frame.blit(dst_x, dst_y, src_width, src_height, &src);
+
+        for y in 0..frame.height as i64 {
+            for x in 0..frame.width as i64 {
+                let (src_x, src_y) = (x - dst_x as i64, y - dst_y as i64);
+                let inside = (0..src_width as i64).contains(&src_x)
+                    && (0..src_height as i64).contains(&src_y);
+                let expected = if inside {
+                    let idx = ((src_y * src_width as i64 + src_x) * 4) as usize;
+                    &src[idx..idx + 4]
+                } else {
+                    &[0; 4][..]
+                };
+
+                let idx = ((y * frame.width as i64 + x) * 4) as usize;
+                assert_eq!(
+                    &frame.buffer[idx..idx + 4],
+                    expected,
+                    "pixel ({x}, {y}) for a {src_width}x{src_height} image at ({dst_x}, {dst_y})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn blit_inside() {
+        assert_blit(0, 0, 4, 3);
+        assert_blit(1, 1, 2, 1);
+        assert_blit(3, 2, 1, 1);
+    }
+
+    #[test]
+    fn blit_crosses_one_edge() {
+        assert_blit(-1, 0, 3, 2); // left
+        assert_blit(0, -2, 3, 3); // top
+        assert_blit(2, 1, 3, 2); // right
+        assert_blit(1, 2, 2, 3); // bottom
+    }
+
+    #[test]
+    fn blit_crosses_corners() {
+        assert_blit(-1, -1, 2, 2); // top-left
+        assert_blit(3, -1, 2, 2); // top-right
+        assert_blit(-1, 2, 2, 2); // bottom-left
+        assert_blit(3, 2, 2, 2); // bottom-right
+    }
+
+    #[test]
+    fn blit_larger_than_frame() {
+        assert_blit(-2, -2, 8, 7);
+        assert_blit(-1, 0, 6, 3);
+        assert_blit(0, -1, 4, 5);
+    }
+
+    #[test]
+    fn blit_touching_edges_from_outside() {
+        assert_blit(-3, 0, 3, 3); // left
+        assert_blit(0, -3, 4, 3); // top
+        assert_blit(4, 0, 3, 3); // right
+        assert_blit(0, 3, 4, 3); // bottom
+    }
+
+    #[test]
+    fn blit_far_outside() {
+        assert_blit(-100, -100, 2, 2);
+        assert_blit(100, 100, 2, 2);
+        assert_blit(-100, 1, 2, 1);
+        assert_blit(1, 100, 1, 2);
+    }
+
+    #[test]
+    fn blit_extreme_offsets_do_not_overflow() {
+        assert_blit(i32::MIN, i32::MIN, 2, 2);
+        assert_blit(i32::MAX, i32::MAX, 2, 2);
+        assert_blit(i32::MIN, 0, 2, 2);
+        assert_blit(0, i32::MAX, 2, 2);
+    }
+
+    #[test]
+    fn blit_empty_image() {
+        assert_blit(0, 0, 0, 0);
+        assert_blit(1, 1, 0, 2);
+        assert_blit(-1, -1, 2, 0);
+    }
+
+    #[test]
+    fn blit_onto_empty_frame() {
+        let mut frame = Frame::new(0, 0, 0, 0, 0);
+        frame.blit(-1, -1, 2, 2, &numbered_image(2, 2));
+        assert!(frame.buffer.is_empty());
+    }
+
+    #[test]
+    fn blit_blends_over_existing_pixels() {
+        let mut frame = Frame::from_bytes(0, 0, 2, 1, &[0, 0, 255, 255, 0, 0, 255, 255], 0);
+        // Half transparent red, crossing the left edge so that only the first pixel is covered
+        let red = Frame::from_bytes(0, 0, 2, 1, &[0, 0, 0, 0, 255, 0, 0, 128], 0);
+        frame.blit(-1, 0, 2, 1, &red.buffer);
+
+        assert_eq!(&frame.buffer[..4], &[128, 0, 127, 255]);
+        assert_eq!(&frame.buffer[4..], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn blit_mixed_row() {
+        // Opaque, transparent and translucent pixels on the same row
+        let mut frame = Frame::from_bytes(0, 0, 3, 1, &[0, 0, 255, 255].repeat(3), 0);
+        let src = Frame::from_bytes(
+            0,
+            0,
+            3,
+            1,
+            &[255, 0, 0, 255, 255, 0, 0, 0, 255, 0, 0, 64],
+            0,
+        );
+        frame.blit(0, 0, 3, 1, &src.buffer);
+
+        assert_eq!(
+            frame.buffer,
+            [255, 0, 0, 255, 0, 0, 255, 255, 64, 0, 191, 255]
+        );
+    }
+
+    #[test]
+    fn blit_over_transparent_keeps_source() {
+        let mut frame = Frame::new(0, 0, 1, 1, 0);
+        let src = Frame::from_bytes(0, 0, 1, 1, &[200, 100, 50, 100], 0);
+        frame.blit(0, 0, 1, 1, &src.buffer);
+
+        assert_eq!(frame.buffer, src.buffer);
+    }
+
+    #[test]
+    fn scale_pixel_rounds_every_channel() {
+        for factor in 0..=255 {
+            for channel in 0..=255 {
+                let expected = (channel as f32 * factor as f32 / 255.0).round() as u32;
+                assert_eq!(mul_div255(channel, factor), expected);
+
+                let pixel = u32::from_le_bytes([channel as u8, 255 - channel as u8, 7, 255]);
+                let scaled = scale_pixel(pixel, factor).to_le_bytes();
+                assert_eq!(
+                    scaled,
+                    [
+                        expected as u8,
+                        mul_div255(255 - channel, factor) as u8,
+                        mul_div255(7, factor) as u8,
+                        factor as u8,
+                    ]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn straight_rgba_round_trip() {
+        let pixels = [10, 20, 30, 255, 0, 0, 0, 0, 255, 128, 0, 200];
+        let frame = Frame::from_bytes(0, 0, 3, 1, &pixels, 0);
+        assert_eq!(frame.to_straight_rgba(), pixels);
+    }
+}
@@ -0,0 +1,14 @@
+pub mod compositor;
+mod effects;
+pub mod frame;
+pub mod overlay;
+pub mod rasterizer;
+pub mod rect;
+pub mod transform;
+
+pub use compositor::*;
+pub use frame::*;
+pub use overlay::*;
+pub use rasterizer::*;
+pub use rect::*;
+pub use transform::*;
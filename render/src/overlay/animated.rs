@@ -0,0 +1,486 @@
+use std::io::Cursor;
+
+use ff::Transform;
+use image::{
+    AnimationDecoder, ImageFormat, Rgba,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+};
+
+use crate::{
+    frame::Frame,
+    overlay::Overlay,
+    rect::Rect,
+    transform::{MAX_DIMENSION, apply_transform, transformed_size},
+};
+
+/// Most pixels of all the frames of an animation together, 1 GiB once in RGBA.
+///
+/// Checked while decoding and once transformed. Each frame is already limited to `MAX_DIMENSION`,
+/// but a hundred of them at that size would not fit in memory.
+const MAX_ANIMATION_PIXELS: u64 = 4 * MAX_DIMENSION as u64 * MAX_DIMENSION as u64;
+
+pub struct AnimatedOverlay {
+    pub frames: Vec<Frame>,
+    z_index: u32,
+}
+
+impl AnimatedOverlay {
+    pub fn from_bytes(bytes: &[u8], x: i32, y: i32, z_index: u32) -> anyhow::Result<Self> {
+        let frames = decode_frames(bytes, MAX_ANIMATION_PIXELS)?
+            .into_iter()
+            .map(|frame| Frame {
+                offset_left: x,
+                offset_top: y,
+                ..frame
+            })
+            .collect();
+
+        Ok(Self { frames, z_index })
+    }
+
+    /// Resample every frame according to `transform`, see `apply_transform`.
+    pub fn with_transform(
+        mut self,
+        transform: &Transform,
+        canvas: (u32, u32),
+    ) -> anyhow::Result<Self> {
+        // Checked before resampling anything
+        let mut pixels = 0;
+        for frame in &self.frames {
+            let (width, height) = transformed_size(frame.width, frame.height, transform, canvas)?;
+            pixels += width as u64 * height as u64;
+        }
+        anyhow::ensure!(
+            pixels <= MAX_ANIMATION_PIXELS,
+            "the frames of an animation cannot have more than {MAX_ANIMATION_PIXELS} pixels together, got {pixels}"
+        );
+
+        self.frames = self
+            .frames
+            .into_iter()
+            .map(|frame| apply_transform(frame, transform, canvas))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(self)
+    }
+
+    fn current_frame_index(&self, timestamp_ms: u128) -> usize {
+        if self.frames.is_empty() {
+            return 0;
+        }
+
+        // compute total animation duration
+        let total_duration: u128 = self.frames.iter().map(|f| f.delay_ms).sum();
+        if total_duration == 0 {
+            return 0; // static single-frame
+        }
+
+        // loop by default
+        let mut time_in_cycle = timestamp_ms % total_duration;
+
+        // find the frame corresponding to the elapsed time
+        for (i, frame) in self.frames.iter().enumerate() {
+            if time_in_cycle < frame.delay_ms {
+                return i;
+            }
+            time_in_cycle -= frame.delay_ms;
+        }
+
+        // fallback to last frame
+        self.frames.len() - 1
+    }
+
+    /// Compute how many ms remain until the end of the current frame.
+    fn time_remaining_on_current_frame(&self, timestamp_ms: u128) -> Option<u128> {
+        if self.frames.is_empty() {
+            return None;
+        }
+
+        let total_duration: u128 = self.frames.iter().map(|f| f.delay_ms).sum();
+        if total_duration == 0 {
+            return None;
+        }
+
+        let mut time_in_cycle = timestamp_ms % total_duration;
+
+        for frame in &self.frames {
+            let dur = frame.delay_ms;
+            if time_in_cycle < dur {
+                return Some(dur - time_in_cycle);
+            }
+            time_in_cycle -= dur;
+        }
+
+        // should not reach here, but fallback to first frame duration
+        Some(self.frames[0].delay_ms)
+    }
+}
+
+/// Decode the frames of a GIF, APNG or WebP, the format being guessed from the magic bytes.
+///
+/// Frames are full canvases, the decoders compose each one over the previous ones according to
+/// the disposal and blend operations of the format. Still images give a single frame.
+///
+/// Frames are decoded one at a time, decoding stops once they have more than `max_pixels` together.
+fn decode_frames(bytes: &[u8], max_pixels: u64) -> anyhow::Result<Vec<Frame>> {
+    let cursor = Cursor::new(bytes);
+    let frames = match image::guess_format(bytes)? {
+        ImageFormat::Gif => GifDecoder::new(cursor)?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(cursor)?;
+            if !decoder.is_apng()? {
+                return still_frame(bytes);
+            }
+            decoder.apng()?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let mut decoder = WebPDecoder::new(cursor)?;
+            if !decoder.has_animation() {
+                return still_frame(bytes);
+            }
+            // Frames are only disposed of once a background color is set. Like browsers, we
+            // ignore the one from the file and let the canvas show through.
+            decoder.set_background_color(Rgba([0, 0, 0, 0]))?;
+            decoder.into_frames()
+        }
+        _ => return still_frame(bytes),
+    };
+
+    let mut decoded = Vec::new();
+    let mut pixels = 0;
+    for frame in frames {
+        let frame = frame?;
+        let (width, height) = frame.buffer().dimensions();
+        pixels += width as u64 * height as u64;
+        anyhow::ensure!(
+            pixels <= max_pixels,
+            "the frames of an animation cannot have more than {max_pixels} pixels together"
+        );
+        decoded.push(to_frame(frame));
+    }
+    Ok(decoded)
+}
+
+fn still_frame(bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+    let rgba = image::load_from_memory(bytes)?.to_rgba8();
+    Ok(vec![to_frame(image::Frame::new(rgba))])
+}
+
+/// Premultiplied `Frame` of a decoded frame, at the origin.
+fn to_frame(frame: image::Frame) -> Frame {
+    // delay in `image` is reprenseted by a fraction, we resolve the fraction
+    let delay_ms = {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        numerator as u128 / denominator as u128
+    };
+
+    let rgba = frame.into_buffer();
+    let (width, height) = rgba.dimensions();
+    Frame::from_bytes(0, 0, width, height, &rgba, delay_ms)
+}
+
+impl Overlay for AnimatedOverlay {
+    fn z_index(&self) -> u32 {
+        self.z_index
+    }
+
+    fn draw(&self, target: &mut Frame, clip: Rect, timestamp_ms: u128) {
+        if self.frames.is_empty() {
+            return;
+        }
+
+        let idx = self.current_frame_index(timestamp_ms);
+        let frame = &self.frames[idx];
+
+        target.blit_clipped(
+            clip,
+            frame.offset_left,
+            frame.offset_top,
+            frame.width,
+            frame.height,
+            &frame.buffer,
+        );
+    }
+
+    fn bounds(&self, timestamp_ms: u128) -> Rect {
+        self.frames
+            .get(self.current_frame_index(timestamp_ms))
+            .map(Frame::bounds)
+            .unwrap_or_default()
+    }
+
+    fn damage(&self, previous_ms: u128, timestamp_ms: u128) -> Vec<Rect> {
+        let previous = self.current_frame_index(previous_ms);
+        let current = self.current_frame_index(timestamp_ms);
+        if previous == current {
+            return Vec::new();
+        }
+
+        // Both frames, in case they do not cover the same area
+        vec![self.bounds(previous_ms), self.bounds(timestamp_ms)]
+    }
+
+    fn time_to_next_frame_ms(&self, timestamp_ms: u128) -> Option<u128> {
+        self.time_remaining_on_current_frame(timestamp_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{
+        Delay, ExtendedColorType, ImageEncoder, RgbaImage,
+        codecs::{gif::GifEncoder, png::PngEncoder, webp::WebPEncoder},
+    };
+
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn image(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    /// A chunk of a RIFF container, padded to an even size.
+    fn chunk(fourcc: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = [fourcc, &(data.len() as u32).to_le_bytes(), data].concat();
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn u24(value: u32) -> [u8; 3] {
+        let [a, b, c, _] = value.to_le_bytes();
+        [a, b, c]
+    }
+
+    /// An animated WebP of `frames`, placed at their offset and disposed of after being shown.
+    fn animated_webp(width: u32, height: u32, frames: &[(u32, u32, RgbaImage)]) -> Vec<u8> {
+        let header = [
+            [0x12, 0, 0, 0].as_slice(),
+            &u24(width - 1),
+            &u24(height - 1),
+        ]
+        .concat();
+        let mut chunks = [chunk(b"VP8X", &header), chunk(b"ANIM", &[0; 6])].concat();
+
+        for (x, y, image) in frames {
+            let mut still = Vec::new();
+            WebPEncoder::new_lossless(&mut still)
+                .write_image(
+                    image,
+                    image.width(),
+                    image.height(),
+                    ExtendedColorType::Rgba8,
+                )
+                .unwrap();
+            let frame = [
+                u24(x / 2).as_slice(),
+                &u24(y / 2),
+                &u24(image.width() - 1),
+                &u24(image.height() - 1),
+                &u24(100),
+                // Dispose of the frame, with alpha blending
+                &[0b01],
+                // Image chunks of the still WebP, after its RIFF header
+                &still[12..],
+            ]
+            .concat();
+            chunks.extend(chunk(b"ANMF", &frame));
+        }
+
+        chunk(b"RIFF", &[b"WEBP".as_slice(), &chunks].concat())
+    }
+
+    /// A frame of an APNG, positioned at (`x`, `y`) and filled with `color`.
+    struct ApngFrame {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        color: [u8; 4],
+        dispose: png::DisposeOp,
+        blend: png::BlendOp,
+    }
+
+    /// An APNG of `width` x `height` pixels, whose first frame is also the default image.
+    fn animated_png(width: u32, height: u32, first: &RgbaImage, frames: &[ApngFrame]) -> Vec<u8> {
+        let mut apng = Vec::new();
+        let mut encoder = png::Encoder::new(&mut apng, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_animated(frames.len() as u32 + 1, 0).unwrap();
+        encoder.set_frame_delay(100, 1000).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(first).unwrap();
+
+        for frame in frames {
+            // The frame has to stay within the image while it moves and shrinks
+            writer.reset_frame_position().unwrap();
+            writer
+                .set_frame_dimension(frame.width, frame.height)
+                .unwrap();
+            writer.set_frame_position(frame.x, frame.y).unwrap();
+            writer.set_dispose_op(frame.dispose).unwrap();
+            writer.set_blend_op(frame.blend).unwrap();
+            writer
+                .write_image_data(&image(frame.width, frame.height, frame.color))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        apng
+    }
+
+    /// Premultiplied RGBA pixel at (`x`, `y`) of a frame.
+    fn pixel(frame: &Frame, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * frame.width + x) * 4) as usize;
+        frame.buffer[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn gif_frames_are_decoded_with_their_delays() {
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames([RED, BLUE].map(|color| {
+                image::Frame::from_parts(
+                    image(2, 2, color),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(50, 1),
+                )
+            }))
+            .unwrap();
+
+        let overlay = AnimatedOverlay::from_bytes(&gif, 0, 0, 0).unwrap();
+        assert_eq!(overlay.frames.len(), 2);
+        assert_eq!(overlay.frames[0].delay_ms, 50);
+        assert_eq!(&overlay.frames[1].buffer[..4], BLUE);
+    }
+
+    #[test]
+    fn apng_frames_are_composed() {
+        // Red on the left half, then translucent blue over the middle, then green in a corner
+        let first = RgbaImage::from_fn(8, 4, |x, _| Rgba(if x < 4 { RED } else { [0; 4] }));
+        let apng = animated_png(
+            8,
+            4,
+            &first,
+            &[
+                ApngFrame {
+                    x: 2,
+                    y: 0,
+                    width: 4,
+                    height: 4,
+                    color: [0, 0, 255, 128],
+                    dispose: png::DisposeOp::Previous,
+                    blend: png::BlendOp::Over,
+                },
+                ApngFrame {
+                    x: 0,
+                    y: 0,
+                    width: 2,
+                    height: 2,
+                    color: [0, 255, 0, 255],
+                    dispose: png::DisposeOp::None,
+                    blend: png::BlendOp::Over,
+                },
+            ],
+        );
+
+        let overlay = AnimatedOverlay::from_bytes(&apng, 0, 0, 0).unwrap();
+        assert_eq!(overlay.frames.len(), 3);
+        assert!(
+            overlay
+                .frames
+                .iter()
+                .all(|frame| (frame.width, frame.height, frame.delay_ms) == (8, 4, 100))
+        );
+
+        let [first, blended, last] = &overlay.frames[..] else {
+            unreachable!()
+        };
+        assert_eq!(pixel(first, 3, 0), RED);
+        assert_eq!(pixel(first, 5, 0), [0; 4]);
+
+        // Blended over the red (the decoder rounds its alpha), alone over the transparent half
+        let [r, g, b, a] = pixel(blended, 3, 0);
+        assert!(
+            r > 100 && g == 0 && b > 100 && a >= 254,
+            "{:?}",
+            [r, g, b, a]
+        );
+        assert_eq!(pixel(blended, 5, 0)[3], 128);
+        assert_eq!(pixel(blended, 1, 0), RED);
+
+        // The blue frame is disposed of, the canvas is back to the first frame below the green one
+        assert_eq!(pixel(last, 0, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(last, 3, 0), RED);
+        assert_eq!(pixel(last, 3, 3), RED);
+        assert_eq!(pixel(last, 5, 0), [0; 4]);
+    }
+
+    #[test]
+    fn disposed_webp_frames_are_cleared() {
+        let webp = animated_webp(4, 2, &[(0, 0, image(2, 2, RED)), (2, 0, image(2, 2, BLUE))]);
+
+        let overlay = AnimatedOverlay::from_bytes(&webp, 0, 0, 0).unwrap();
+        assert_eq!(overlay.frames.len(), 2);
+        let last = &overlay.frames[1];
+        assert_eq!((last.width, last.height, last.delay_ms), (4, 2, 100));
+        // Only the second frame is left, the blending of the decoder rounds its colors
+        assert_eq!(last.buffer[3], 0);
+        assert_eq!(last.buffer[11], 255);
+    }
+
+    #[test]
+    fn still_images_are_a_single_frame() {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(&image(2, 2, RED), 2, 2, ExtendedColorType::Rgba8)
+            .unwrap();
+
+        let overlay = AnimatedOverlay::from_bytes(&png, 0, 0, 0).unwrap();
+        assert_eq!(overlay.frames.len(), 1);
+        assert_eq!(overlay.frames[0].delay_ms, 0);
+    }
+
+    #[test]
+    fn decoding_stops_at_the_pixel_limit() {
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames((0..10).map(|i| {
+                let color = if i % 2 == 0 { RED } else { BLUE };
+                image::Frame::from_parts(
+                    image(4, 4, color),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(50, 1),
+                )
+            }))
+            .unwrap();
+
+        assert_eq!(decode_frames(&gif, 160).unwrap().len(), 10);
+        assert!(decode_frames(&gif, 159).is_err());
+    }
+
+    #[test]
+    fn oversized_animations_are_rejected() {
+        let overlay = AnimatedOverlay {
+            frames: (0..5).map(|_| Frame::new(0, 0, 1, 1, 100)).collect(),
+            z_index: 0,
+        };
+        // Each frame fits, but not all of them together
+        let transform = Transform {
+            size: ff::Size::Pixels {
+                width: Some(MAX_DIMENSION),
+                height: Some(MAX_DIMENSION),
+            },
+            ..Transform::default()
+        };
+        assert!(overlay.with_transform(&transform, (1920, 1080)).is_err());
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        assert!(AnimatedOverlay::from_bytes(b"not an image", 0, 0, 0).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::Cursor;
+
+use image::ImageReader;
+
+use ff::Transform;
+
+use crate::{frame::Frame, overlay::Overlay, rect::Rect, transform::apply_transform};
+
+pub struct ImageOverlay {
+    z_index: u32,
+    pub frame: Frame,
+}
+
+impl ImageOverlay {
+    pub fn from_bytes(bytes: &[u8], left: i32, top: i32, z_index: u32) -> anyhow::Result<Self> {
+        let rgba = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .decode()?
+            .to_rgba8();
+
+        let (width, height) = rgba.dimensions();
+        let frame = Frame::from_bytes(left, top, width, height, &rgba, 0);
+
+        Ok(Self { z_index, frame })
+    }
+
+    /// Resample the overlay according to `transform`, see `apply_transform`.
+    pub fn with_transform(
+        mut self,
+        transform: &Transform,
+        canvas: (u32, u32),
+    ) -> anyhow::Result<Self> {
+        self.frame = apply_transform(self.frame, transform, canvas)?;
+        Ok(self)
+    }
+}
+
+impl Overlay for ImageOverlay {
+    fn z_index(&self) -> u32 {
+        self.z_index
+    }
+
+    fn draw(&self, target: &mut Frame, clip: Rect, _timestamp_ms: u128) {
+        target.blit_clipped(
+            clip,
+            self.frame.offset_left,
+            self.frame.offset_top,
+            self.frame.width,
+            self.frame.height,
+            &self.frame.buffer,
+        );
+    }
+
+    fn bounds(&self, _timestamp_ms: u128) -> Rect {
+        self.frame.bounds()
+    }
+
+    fn time_to_next_frame_ms(&self, _timestamp_ms: u128) -> Option<u128> {
+        None
+    }
+}
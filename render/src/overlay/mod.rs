@@ -5,5 +5,5 @@ mod traits;
 
 pub use animated::AnimatedOverlay;
 pub use image::ImageOverlay;
-pub use text::TextOverlay;
+pub use text::{TextOverlay, TextRun, TextStyle};
 pub use traits::Overlay;
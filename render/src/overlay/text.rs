@@ -0,0 +1,530 @@
+use cosmic_text::{
+    Align, Attrs, Buffer, CacheKeyFlags, Color, Family, FontSystem, Metrics, Shaping, Style,
+    SwashCache, SwashContent, SwashImage, Weight, fontdb::Query,
+};
+
+use ff::{TextAlign, TextEffects, TextLayout, Transform};
+
+use crate::{
+    effects::{colorize, decorate},
+    frame::{Frame, mul_div255},
+    overlay::Overlay,
+    rect::Rect,
+    transform::{MAX_DIMENSION, apply_transform},
+};
+
+/// Biggest font size, in pixels, so that a single glyph cannot exhaust the memory.
+const MAX_FONT_SIZE: u32 = 1024;
+
+/// Static text overlay rasterized as a bitmap.
+pub struct TextOverlay {
+    z_index: u32,
+    pub frame: Frame,
+}
+
+/// A run of text sharing the same style, see `ff::TextSpan`.
+pub struct TextRun<'a> {
+    pub text: &'a str,
+    /// Family of a font loaded in the `FontSystem`.
+    pub family: &'a str,
+    /// Thickness of the font, from 100 (thin) to 900 (black).
+    pub weight: u16,
+    pub italic: bool,
+    /// Font size in pixels.
+    pub font_size: u32,
+    /// Straight RGBA color.
+    pub color: [u8; 4],
+}
+
+/// Everything that defines how some text looks, see `ff::Overlay::Text`.
+pub struct TextStyle<'a> {
+    pub runs: Vec<TextRun<'a>>,
+    pub layout: TextLayout,
+    pub effects: TextEffects,
+}
+
+impl TextOverlay {
+    /// Create a text overlay by shaping and rasterizing text into a frame.
+    ///
+    /// The frame is as large as the text, its top-left corner being at (`left`, `top`).
+    pub fn from_bytes(
+        font_manager: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        style: &TextStyle,
+        left: i32,
+        top: i32,
+        z_index: u32,
+    ) -> anyhow::Result<Self> {
+        let layout = &style.layout;
+        anyhow::ensure!(
+            layout.line_height.is_finite() && layout.line_height > 0.0,
+            "the line height must be a positive number"
+        );
+        let Some(first) = style.runs.first() else {
+            anyhow::bail!("the text has no run");
+        };
+        let spans = style
+            .runs
+            .iter()
+            .map(|run| Ok((run.text, run_attrs(font_manager, run, layout.line_height)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // Only used for the empty lines, every run brings its own attributes
+        let default_attrs = spans[0].1.clone();
+        let font_size = first.font_size as f32;
+        let metrics = Metrics::new(font_size, font_size * layout.line_height);
+
+        // Prepare the shaping buffer
+        let mut buffer = Buffer::new(font_manager, metrics);
+        let mut buf = buffer.borrow_with(font_manager);
+
+        buf.set_size(layout.max_width.map(|width| width as f32), None);
+
+        let align = match layout.align {
+            TextAlign::Left => Align::Left,
+            TextAlign::Center => Align::Center,
+            TextAlign::Right => Align::Right,
+            TextAlign::Justify => Align::Justified,
+        };
+
+        buf.set_rich_text(spans, &default_attrs, Shaping::Advanced, Some(align));
+        buf.shape_until_scroll(true);
+
+        // Without a max width, every paragraph is aligned on its own longest line,
+        // they are aligned on the longest line of the whole text instead
+        if layout.max_width.is_none() && align != Align::Left {
+            let width = buf.layout_runs().map(|run| run.line_w).fold(0.0, f32::max);
+            buf.set_size(Some(width.ceil()), None);
+            buf.shape_until_scroll(true);
+        }
+
+        // Checked before rasterizing anything, glyphs only overflow from the lines by a fraction of the font size
+        let bounds = layout_bounds(&buf);
+        anyhow::ensure!(
+            bounds.width <= MAX_DIMENSION && bounds.height <= MAX_DIMENSION,
+            "the text cannot be larger than {MAX_DIMENSION}x{MAX_DIMENSION} pixels, got {}x{}",
+            bounds.width,
+            bounds.height
+        );
+        let glyphs = rasterize_glyphs(&buffer, font_manager, swash_cache, first.color);
+        // Glyphs can be drawn a bit outside of the lines (italics, accents, ...), the frame covers them too
+        let area = glyphs
+            .iter()
+            .fold(bounds, |area, glyph| area.bounding(&glyph.bounds()));
+        anyhow::ensure!(
+            area.width <= MAX_DIMENSION && area.height <= MAX_DIMENSION,
+            "the text cannot be larger than {MAX_DIMENSION}x{MAX_DIMENSION} pixels, got {}x{}",
+            area.width,
+            area.height
+        );
+
+        // Glyphs can overlap (accents, scripts with ligatures, tight fonts, ...), they are blended together
+        let mut text = Frame::new(area.left, area.top, area.width, area.height, 0);
+        for glyph in &glyphs {
+            text.blit(
+                glyph.offset_left - area.left,
+                glyph.offset_top - area.top,
+                glyph.width,
+                glyph.height,
+                &glyph.buffer,
+            );
+        }
+
+        let mut frame = decorate(text, bounds, &style.effects)?;
+        // (`left`, `top`) is the corner of the lines, not of what overflows from them
+        frame.offset_left = frame
+            .offset_left
+            .saturating_add(left.saturating_sub(bounds.left));
+        frame.offset_top = frame
+            .offset_top
+            .saturating_add(top.saturating_sub(bounds.top));
+
+        Ok(Self { z_index, frame })
+    }
+
+    /// Resample the overlay according to `transform`, see `apply_transform`.
+    pub fn with_transform(
+        mut self,
+        transform: &Transform,
+        canvas: (u32, u32),
+    ) -> anyhow::Result<Self> {
+        self.frame = apply_transform(self.frame, transform, canvas)?;
+        Ok(self)
+    }
+}
+
+impl Overlay for TextOverlay {
+    fn z_index(&self) -> u32 {
+        self.z_index
+    }
+
+    fn draw(&self, target: &mut Frame, clip: Rect, _timestamp_ms: u128) {
+        target.blit_clipped(
+            clip,
+            self.frame.offset_left,
+            self.frame.offset_top,
+            self.frame.width,
+            self.frame.height,
+            &self.frame.buffer,
+        );
+    }
+
+    fn bounds(&self, _timestamp_ms: u128) -> Rect {
+        self.frame.bounds()
+    }
+
+    fn time_to_next_frame_ms(&self, _timestamp_ms: u128) -> Option<u128> {
+        None
+    }
+}
+
+/// Rasterize every glyph of `buffer` into its own frame, positioned in the coordinates of the buffer.
+///
+/// Glyphs are drawn with their own color, or `default_color` (straight RGBA).
+/// Color glyphs (emoji) keep their colors, only the opacity of the text applies to them.
+fn rasterize_glyphs(
+    buffer: &Buffer,
+    font_manager: &mut FontSystem,
+    swash_cache: &mut SwashCache,
+    default_color: [u8; 4],
+) -> Vec<Frame> {
+    let mut glyphs = Vec::new();
+    for run in buffer.layout_runs() {
+        for glyph in run.glyphs {
+            let physical = glyph.physical((0.0, 0.0), 1.0);
+            let Some(image) = swash_cache.get_image(font_manager, physical.cache_key) else {
+                continue;
+            };
+            let placement = image.placement;
+            if placement.width == 0 || placement.height == 0 {
+                // e.g. spaces
+                continue;
+            }
+
+            let color = glyph
+                .color_opt
+                .map_or(default_color, |color| color.as_rgba());
+            glyphs.push(Frame {
+                offset_left: physical.x + placement.left,
+                offset_top: run.line_y as i32 + physical.y - placement.top,
+                width: placement.width,
+                height: placement.height,
+                buffer: glyph_pixels(image, color),
+                delay_ms: 0,
+            });
+        }
+    }
+    glyphs
+}
+
+/// Premultiplied RGBA pixels of a glyph image, drawn with a straight RGBA `color`.
+fn glyph_pixels(image: &SwashImage, color: [u8; 4]) -> Vec<u8> {
+    match image.content {
+        SwashContent::Mask => colorize(&image.data, color),
+        SwashContent::SubpixelMask => {
+            // One coverage per color channel, meant for LCD screens which splash-screens cannot rely on
+            let coverage: Vec<u8> = image
+                .data
+                .chunks_exact(4)
+                .map(|px| ((px[0] as u32 + px[1] as u32 + px[2] as u32) / 3) as u8)
+                .collect();
+            colorize(&coverage, color)
+        }
+        SwashContent::Color => {
+            let opacity = color[3] as u32;
+            let mut pixels = Vec::with_capacity(image.data.len());
+            for px in image.data.chunks_exact(4) {
+                let alpha = mul_div255(px[3] as u32, opacity);
+                pixels.extend_from_slice(&[
+                    mul_div255(px[0] as u32, alpha) as u8,
+                    mul_div255(px[1] as u32, alpha) as u8,
+                    mul_div255(px[2] as u32, alpha) as u8,
+                    alpha as u8,
+                ]);
+            }
+            pixels
+        }
+    }
+}
+
+/// Attributes shaping `run` with the face of its family that is the closest to its weight and style.
+fn run_attrs<'a>(
+    font_manager: &FontSystem,
+    run: &TextRun<'a>,
+    line_height: f32,
+) -> anyhow::Result<Attrs<'a>> {
+    anyhow::ensure!(
+        (1..=MAX_FONT_SIZE).contains(&run.font_size),
+        "the font size must be between 1 and {MAX_FONT_SIZE}, got {}",
+        run.font_size
+    );
+    anyhow::ensure!(
+        (1..=1000).contains(&run.weight),
+        "the font weight must be between 1 and 1000, got {}",
+        run.weight
+    );
+
+    let font_size = run.font_size as f32;
+    let [r, g, b, a] = run.color;
+    let mut attrs = Attrs::new()
+        .color(Color::rgba(r, g, b, a))
+        .family(Family::Name(run.family))
+        .weight(Weight(run.weight))
+        .style(if run.italic {
+            Style::Italic
+        } else {
+            Style::Normal
+        })
+        .metrics(Metrics::new(font_size, font_size * line_height));
+
+    // Faces whose weight or style differ from the requested ones are skipped by the shaping, in favor of another
+    // family, so the closest face of the family is asked for instead.
+    // A family may lack a bold face, or have a regular one that is not the usual 400 (e.g. "Fira Mono" starts at 500)
+    let query = Query {
+        families: &[Family::Name(run.family)],
+        weight: attrs.weight,
+        style: attrs.style,
+        ..Query::default()
+    };
+    let face = font_manager
+        .db()
+        .query(&query)
+        .and_then(|id| font_manager.db().face(id));
+    if let Some(face) = face {
+        attrs = attrs.weight(face.weight).style(face.style);
+        if run.italic && face.style == Style::Normal {
+            // Slanted rather than upright
+            attrs = attrs.cache_key_flags(CacheKeyFlags::FAKE_ITALIC);
+        }
+    }
+
+    Ok(attrs)
+}
+
+/// Area covered by the lines of text, from the leftmost to the rightmost glyph and from the top of the first line
+/// to the bottom of the last one.
+fn layout_bounds(buffer: &Buffer) -> Rect {
+    let (mut left, mut right, mut bottom) = (f32::INFINITY, f32::NEG_INFINITY, 0.0_f32);
+    for run in buffer.layout_runs() {
+        for glyph in run.glyphs {
+            left = left.min(glyph.x);
+            right = right.max(glyph.x + glyph.w);
+        }
+        bottom = bottom.max(run.line_top + run.line_height);
+    }
+
+    if left > right {
+        // No glyph at all, only empty lines
+        return Rect::new(0, 0, 0, bottom.ceil() as u32);
+    }
+    Rect::new(
+        left.floor() as i32,
+        0,
+        (right.ceil() - left.floor()) as u32,
+        bottom.ceil() as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use cosmic_text::fontdb::Database;
+
+    use super::*;
+
+    /// Style of `text` in white Noto Sans.
+    fn style(text: &str, font_size: u32, layout: TextLayout) -> TextStyle<'_> {
+        TextStyle {
+            runs: vec![TextRun {
+                text,
+                family: "Noto Sans",
+                weight: 400,
+                italic: false,
+                font_size,
+                color: [255; 4],
+            }],
+            layout,
+            effects: TextEffects::default(),
+        }
+    }
+
+    /// Lay text out at (`left`, `top`) with the bundled Noto Sans alone in its font system,
+    /// so that the result does not depend on the machine.
+    fn rasterize(style: &TextStyle, left: i32, top: i32) -> anyhow::Result<Frame> {
+        let mut db = Database::new();
+        db.load_font_data(notosans::REGULAR_TTF.to_vec());
+        db.load_font_data(notosans::BOLD_TTF.to_vec());
+        let mut font_manager = FontSystem::new_with_locale_and_db("en-US".to_string(), db);
+        let overlay = TextOverlay::from_bytes(
+            &mut font_manager,
+            &mut SwashCache::new(),
+            style,
+            left,
+            top,
+            0,
+        )?;
+        Ok(overlay.frame)
+    }
+
+    fn render(text: &str, font_size: u32, layout: TextLayout) -> anyhow::Result<Frame> {
+        rasterize(&style(text, font_size, layout), 0, 0)
+    }
+
+    /// Horizontal extent of the drawn pixels whose `y` is within `lines`, in the coordinates of the text.
+    fn ink(frame: &Frame, lines: Range<i32>) -> Range<i32> {
+        let (mut left, mut right) = (i32::MAX, i32::MIN);
+        for row in 0..frame.height as i32 {
+            if !lines.contains(&(frame.offset_top + row)) {
+                continue;
+            }
+            for col in 0..frame.width as i32 {
+                let alpha = frame.buffer[((row * frame.width as i32 + col) * 4 + 3) as usize];
+                if alpha > 0 {
+                    left = left.min(frame.offset_left + col);
+                    right = right.max(frame.offset_left + col + 1);
+                }
+            }
+        }
+        left..right
+    }
+
+    /// Lines of 20 px text are 24 px tall with the default line height.
+    const FIRST_LINE: Range<i32> = 0..24;
+    const SECOND_LINE: Range<i32> = 24..48;
+
+    fn aligned(align: TextAlign) -> (Range<i32>, Range<i32>) {
+        let layout = TextLayout {
+            align,
+            ..TextLayout::default()
+        };
+        let frame = render("WWWWWW\nii", 20, layout).unwrap();
+        (ink(&frame, FIRST_LINE), ink(&frame, SECOND_LINE))
+    }
+
+    #[test]
+    fn frames_are_as_wide_as_the_text() {
+        let short = render("ii", 20, TextLayout::default()).unwrap();
+        let long = render("iiiiiiii", 20, TextLayout::default()).unwrap();
+        let wide = render("WWWWWWWW", 20, TextLayout::default()).unwrap();
+
+        assert!(short.width < long.width && long.width < wide.width);
+        assert_eq!(short.height, wide.height);
+    }
+
+    #[test]
+    fn lines_wrap_at_the_max_width() {
+        let text = "wrap these words";
+        let unwrapped = render(text, 20, TextLayout::default()).unwrap();
+        let layout = TextLayout {
+            max_width: Some(80),
+            ..TextLayout::default()
+        };
+        let wrapped = render(text, 20, layout).unwrap();
+
+        assert!(unwrapped.width > 80);
+        assert!(wrapped.width <= 80);
+        assert!(!ink(&wrapped, SECOND_LINE).is_empty());
+        assert!(!ink(&wrapped, 48..72).is_empty());
+    }
+
+    #[test]
+    fn lines_are_aligned_on_the_longest_one() {
+        let (first, second) = aligned(TextAlign::Left);
+        assert!((first.start - second.start).abs() <= 2);
+
+        let (first, second) = aligned(TextAlign::Center);
+        assert!(second.start > first.start + 20);
+        assert!(((first.start + first.end) - (second.start + second.end)).abs() <= 2);
+
+        let (first, second) = aligned(TextAlign::Right);
+        assert!(second.start > first.start + 40);
+        assert!((first.end - second.end).abs() <= 2);
+    }
+
+    fn image(content: SwashContent, data: Vec<u8>) -> SwashImage {
+        SwashImage {
+            content,
+            data,
+            ..SwashImage::default()
+        }
+    }
+
+    /// "plain " followed by `styled`, which is changed by `restyle`.
+    fn with_span(restyle: impl FnOnce(&mut TextRun)) -> Frame {
+        let mut text = style("plain ", 20, TextLayout::default());
+        let mut span = style("styled", 20, TextLayout::default()).runs.remove(0);
+        restyle(&mut span);
+        text.runs.push(span);
+        rasterize(&text, 0, 0).unwrap()
+    }
+
+    /// Sum of the opacity of every pixel.
+    fn coverage(frame: &Frame) -> u64 {
+        frame.buffer.chunks_exact(4).map(|px| px[3] as u64).sum()
+    }
+
+    #[test]
+    fn spans_are_drawn_with_their_own_style() {
+        let plain = with_span(|_| ());
+
+        let red = with_span(|span| span.color = [255, 0, 0, 255]);
+        assert_eq!((red.width, red.height), (plain.width, plain.height));
+        assert_ne!(red.buffer, plain.buffer);
+        // Both colors are there, the white of the rest of the text and the red of the span
+        let pixels: Vec<_> = red
+            .buffer
+            .chunks_exact(4)
+            .filter(|px| px[3] == 255)
+            .collect();
+        assert!(pixels.iter().any(|px| px[..3] == [255, 0, 0]));
+        assert!(pixels.iter().any(|px| px[..3] == [255, 255, 255]));
+
+        let bold = with_span(|span| span.weight = 700);
+        assert_ne!(bold.buffer, plain.buffer);
+        assert!(coverage(&bold) > coverage(&plain));
+
+        let big = with_span(|span| span.font_size = 40);
+        assert!(big.width > plain.width && big.height > plain.height);
+    }
+
+    #[test]
+    fn masks_are_drawn_with_the_text_color() {
+        let mask = image(SwashContent::Mask, vec![255, 128, 0]);
+        assert_eq!(
+            glyph_pixels(&mask, [255, 0, 0, 255]),
+            [[255, 0, 0, 255], [128, 0, 0, 128], [0, 0, 0, 0]].concat()
+        );
+    }
+
+    #[test]
+    fn color_glyphs_keep_their_colors() {
+        let emoji = image(
+            SwashContent::Color,
+            [[0, 255, 0, 255], [255, 255, 255, 128]].concat(),
+        );
+        // Only the opacity of the text applies
+        assert_eq!(
+            glyph_pixels(&emoji, [255, 0, 0, 128]),
+            [[0, 128, 0, 128], [64, 64, 64, 64]].concat()
+        );
+    }
+
+    #[test]
+    fn far_away_text_does_not_overflow() {
+        let style = style("j", 20, TextLayout::default());
+        let frame = rasterize(&style, i32::MAX, i32::MIN).unwrap();
+        assert_eq!(frame.offset_top, i32::MIN);
+        assert!(frame.offset_left > i32::MAX - 20);
+    }
+
+    #[test]
+    fn oversized_text_is_rejected() {
+        assert!(render("a", MAX_FONT_SIZE + 1, TextLayout::default()).is_err());
+
+        let layout = TextLayout {
+            line_height: 1000.0,
+            ..TextLayout::default()
+        };
+        assert!(render("a\nb", MAX_FONT_SIZE, layout).is_err());
+    }
+}
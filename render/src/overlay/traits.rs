@@ -0,0 +1,31 @@
+use crate::{frame::Frame, rect::Rect};
+
+/// Specifies an element that can be composited onto a `Frame`.
+///
+/// Overlays are built outside of the render loop, hence `Send`.
+pub trait Overlay: Send {
+    /// Z-order for composition (0 = back, high = front).
+    fn z_index(&self) -> u32;
+
+    /// Draw the `Overlay` into the given `Frame` for the specified timestamp.
+    /// Only the pixels inside `clip` may be touched, the rest of the `Frame` is left as is.
+    ///
+    /// Timestamps are relative to the moment the `Overlay` appeared, see `Compositor::render`.
+    fn draw(&self, frame: &mut Frame, clip: Rect, timestamp_ms: u128);
+
+    /// Area of the `Frame` covered by the `Overlay` at the specified timestamp.
+    fn bounds(&self, timestamp_ms: u128) -> Rect;
+
+    /// Areas of the `Frame` that changed between `previous_ms` and `timestamp_ms`, both relative to
+    /// the moment the `Overlay` appeared.
+    ///
+    /// Static overlays never change once drawn, hence the default.
+    fn damage(&self, _previous_ms: u128, _timestamp_ms: u128) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    /// Time in milliseconds until this overlay wants the next `Frame`.
+    /// - Return `None` if this overlay does not have a timed next frame (static image).
+    /// - Return `Some(remaining_ms)` where `remaining_ms` is >= 0.
+    fn time_to_next_frame_ms(&self, timestamp_ms: u128) -> Option<u128>;
+}
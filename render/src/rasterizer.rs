@@ -0,0 +1,433 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use cosmic_text::{FontSystem, SwashCache, Weight, fontdb::Source};
+use ff::{MediaCache, MediaRef, Overlay as LibOverlay, TextSpan};
+
+use crate::{
+    compositor::{Compositor, Lifetime},
+    overlay::{AnimatedOverlay, ImageOverlay, Overlay, TextOverlay, TextRun, TextStyle},
+    transform::resolve_position,
+};
+
+/// Faces of the font used by `Overlay::Text` when none is given, bundled so that text looks the same on every machine.
+const DEFAULT_FONT: [&[u8]; 4] = [
+    notosans::REGULAR_TTF,
+    notosans::BOLD_TTF,
+    notosans::ITALIC_TTF,
+    notosans::BOLD_ITALIC_TTF,
+];
+/// Family of `DEFAULT_FONT`.
+const DEFAULT_FONT_FAMILY: &str = "Noto Sans";
+
+/// Total size of the fonts sent along overlays that are kept loaded, in bytes.
+const MAX_SENT_FONTS_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Turns the overlays received from the server into `Overlay` that can be composited.
+///
+/// Decoding images and shaping text is slow, so this is expected to run outside of the render loop
+/// (`tokio::task::spawn_blocking`).
+pub struct Rasterizer {
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    /// Fonts sent along overlays, which are loaded once and kept for the following batches.
+    ///
+    /// The caches of cosmic-text are never cleared, so removing a single font would not free anything.
+    /// Once they weigh more than `MAX_SENT_FONTS_SIZE`, the whole `FontSystem` is replaced instead.
+    sent_fonts: HashSet<MediaRef>,
+}
+
+impl Default for Rasterizer {
+    fn default() -> Self {
+        Self {
+            font_system: default_font_system(),
+            swash_cache: SwashCache::new(),
+            sent_fonts: HashSet::new(),
+        }
+    }
+}
+
+/// `FontSystem` with the fonts installed on this machine and `DEFAULT_FONT`.
+fn default_font_system() -> FontSystem {
+    let mut font_system = FontSystem::new();
+    for face in DEFAULT_FONT {
+        font_system
+            .db_mut()
+            .load_font_source(Source::Binary(Arc::new(face)));
+    }
+    font_system
+}
+
+impl Rasterizer {
+    /// Decode and rasterize every overlay of a batch, for a canvas of the given dimensions.
+    pub fn rasterize(
+        &mut self,
+        overlays: Vec<LibOverlay>,
+        options: ff::DisplayOptions,
+        media_cache: &MediaCache,
+        canvas: (u32, u32),
+    ) -> anyhow::Result<RasterizedBatch> {
+        self.load_fonts(&overlays, media_cache)?;
+        let overlays = overlays
+            .into_iter()
+            .map(|overlay| self.rasterize_overlay(overlay, media_cache, canvas))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(RasterizedBatch {
+            overlays,
+            timeout_ms: options.timeout_ms,
+        })
+    }
+
+    /// Load the fonts sent along the overlays into the font database, unless they were sent before.
+    ///
+    /// The fonts of previous batches are forgotten when there is no room left for the new ones.
+    fn load_fonts(
+        &mut self,
+        overlays: &[LibOverlay],
+        media_cache: &MediaCache,
+    ) -> anyhow::Result<()> {
+        let mut fonts = HashMap::new();
+        for overlay in overlays {
+            let LibOverlay::Text {
+                font:
+                    Some(ff::Font {
+                        family,
+                        data: Some(data),
+                    }),
+                ..
+            } = overlay
+            else {
+                continue;
+            };
+
+            let media = match data {
+                ff::Media::Inline(bytes) => MediaRef::of(bytes),
+                ff::Media::Ref(media) => *media,
+            };
+            fonts.insert(media, (family, data));
+        }
+
+        let batch_size: u64 = fonts.keys().map(|media| media.len).sum();
+        anyhow::ensure!(
+            batch_size <= MAX_SENT_FONTS_SIZE,
+            "the fonts of the batch weigh {batch_size} bytes, more than {MAX_SENT_FONTS_SIZE}"
+        );
+        fonts.retain(|media, _| !self.sent_fonts.contains(media));
+        let loaded_size: u64 = self.sent_fonts.iter().map(|media| media.len).sum();
+        let new_size: u64 = fonts.keys().map(|media| media.len).sum();
+        if loaded_size + new_size > MAX_SENT_FONTS_SIZE {
+            self.font_system = default_font_system();
+            self.swash_cache = SwashCache::new();
+            self.sent_fonts.clear();
+        }
+
+        for (media, (family, data)) in fonts {
+            let data: Arc<Vec<u8>> = match data {
+                ff::Media::Inline(bytes) => Arc::new(bytes.clone()),
+                ff::Media::Ref(media) => media_cache.get(media).cloned().ok_or_else(|| {
+                    anyhow::anyhow!("font of {} bytes was not received", media.len)
+                })?,
+            };
+            let ids = self
+                .font_system
+                .db_mut()
+                .load_font_source(Source::Binary(data));
+            anyhow::ensure!(
+                !ids.is_empty(),
+                "the font data of {family} could not be parsed"
+            );
+            self.sent_fonts.insert(media);
+        }
+        Ok(())
+    }
+
+    /// Family to render some text with, the default one when `family` is not installed on this machine.
+    /// `is_sent` tells whether the font was sent along the batch, and loaded by `load_fonts` beforehand.
+    fn resolve_family<'a>(&self, family: &'a str, is_sent: bool) -> anyhow::Result<&'a str> {
+        let is_available = self
+            .font_system
+            .db()
+            .faces()
+            .any(|face| face.families.iter().any(|(name, _)| name == family));
+
+        match (is_available, is_sent) {
+            (true, _) => Ok(family),
+            (false, true) => anyhow::bail!("the font data does not contain the {family} family"),
+            (false, false) => Ok(DEFAULT_FONT_FAMILY),
+        }
+    }
+
+    fn rasterize_overlay(
+        &mut self,
+        overlay: LibOverlay,
+        media_cache: &MediaCache,
+        canvas: (u32, u32),
+    ) -> anyhow::Result<PendingOverlay> {
+        let transform = *overlay.transform();
+        let (left, top) = resolve_position(&overlay.position(), canvas)?;
+        let (overlay, start_ms, duration_ms): (Box<dyn Overlay>, _, _) = match overlay {
+            LibOverlay::Image {
+                media,
+                z_index,
+                start_ms,
+                duration_ms,
+                ..
+            } => (
+                Box::new(
+                    ImageOverlay::from_bytes(
+                        media_bytes(&media, media_cache)?,
+                        left,
+                        top,
+                        z_index,
+                    )?
+                    .with_transform(&transform, canvas)?,
+                ),
+                start_ms,
+                duration_ms,
+            ),
+
+            LibOverlay::AnimatedImage {
+                media,
+                z_index,
+                start_ms,
+                duration_ms,
+                ..
+            } => (
+                Box::new(
+                    AnimatedOverlay::from_bytes(
+                        media_bytes(&media, media_cache)?,
+                        left,
+                        top,
+                        z_index,
+                    )?
+                    .with_transform(&transform, canvas)?,
+                ),
+                start_ms,
+                duration_ms,
+            ),
+
+            LibOverlay::Text {
+                text,
+                spans,
+                size,
+                color,
+                layout,
+                font,
+                effects,
+                z_index,
+                start_ms,
+                duration_ms,
+                ..
+            } => {
+                let family = match &font {
+                    Some(font) => self.resolve_family(&font.family, font.data.is_some())?,
+                    None => DEFAULT_FONT_FAMILY,
+                };
+                let spans = if spans.is_empty() {
+                    vec![TextSpan {
+                        text,
+                        ..TextSpan::default()
+                    }]
+                } else {
+                    spans
+                };
+                let runs = spans
+                    .iter()
+                    .map(|span| {
+                        Ok(TextRun {
+                            text: &span.text,
+                            family: match &span.family {
+                                Some(family) => self.resolve_family(family, false)?,
+                                None => family,
+                            },
+                            weight: span.weight.unwrap_or(Weight::NORMAL.0),
+                            italic: span.italic,
+                            font_size: span.size.unwrap_or(size),
+                            color: span.color.unwrap_or(color),
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                let overlay = TextOverlay::from_bytes(
+                    &mut self.font_system,
+                    &mut self.swash_cache,
+                    &TextStyle {
+                        runs,
+                        layout,
+                        effects,
+                    },
+                    left,
+                    top,
+                    z_index,
+                )?
+                .with_transform(&transform, canvas)?;
+                (Box::new(overlay), start_ms, duration_ms)
+            }
+        };
+
+        Ok(PendingOverlay {
+            overlay,
+            start_ms,
+            duration_ms,
+        })
+    }
+}
+
+/// Raw encoded data of an overlay media.
+fn media_bytes<'a>(media: &'a ff::Media, media_cache: &'a MediaCache) -> anyhow::Result<&'a [u8]> {
+    match media {
+        ff::Media::Inline(bytes) => Ok(bytes),
+        ff::Media::Ref(media) => media_cache
+            .get(media)
+            .map(|data| data.as_slice())
+            .ok_or_else(|| anyhow::anyhow!("media of {} bytes was not received", media.len)),
+    }
+}
+
+/// An `Overlay` waiting for its batch to be fired.
+struct PendingOverlay {
+    overlay: Box<dyn Overlay>,
+    /// Delay after the batch is fired.
+    start_ms: u32,
+    duration_ms: Option<u32>,
+}
+
+/// Overlays of a batch, ready to be shown as soon as it is fired.
+pub struct RasterizedBatch {
+    overlays: Vec<PendingOverlay>,
+    timeout_ms: u32,
+}
+
+impl RasterizedBatch {
+    /// Adds the overlays to a `Compositor`.
+    ///
+    /// `fired_at_ms` is the moment the overlays were fired, on the timeline of the `Compositor`.
+    /// Every overlay is gone once `DisplayOptions.timeout_ms` elapsed since that moment.
+    pub fn fire(self, compositor: &mut Compositor, fired_at_ms: u128) {
+        let batch_end_ms = fired_at_ms + self.timeout_ms as u128;
+
+        for pending in self.overlays {
+            let start_ms = fired_at_ms + pending.start_ms as u128;
+            let end_ms = pending.duration_ms.map_or(batch_end_ms, |duration_ms| {
+                batch_end_ms.min(start_ms + duration_ms as u128)
+            });
+            let lifetime = Lifetime {
+                start_ms,
+                end_ms: Some(end_ms),
+            };
+            compositor.add_overlay(pending.overlay, lifetime);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The bundled font, made `len` bytes long and distinct from others by padding it with `padding`.
+    fn font_data(padding: u8, len: usize) -> Vec<u8> {
+        let mut data = notosans::REGULAR_TTF.to_vec();
+        data.resize(len, padding);
+        data
+    }
+
+    fn text(font: ff::Font) -> LibOverlay {
+        LibOverlay::Text {
+            text: "Text".to_string(),
+            spans: Vec::new(),
+            size: 16,
+            color: [255; 4],
+            layout: ff::TextLayout::default(),
+            font: Some(font),
+            effects: ff::TextEffects::default(),
+            offset_left: 0,
+            offset_top: 0,
+            z_index: 0,
+            start_ms: 0,
+            duration_ms: None,
+            transform: ff::Transform::default(),
+            position: None,
+        }
+    }
+
+    /// Load a font sent along a batch, under the family of the bundled font.
+    fn load(rasterizer: &mut Rasterizer, data: Vec<u8>) -> anyhow::Result<()> {
+        let font = ff::Font {
+            family: DEFAULT_FONT_FAMILY.to_string(),
+            data: Some(ff::Media::Inline(data)),
+        };
+        rasterizer.load_fonts(&[text(font)], &MediaCache::default())
+    }
+
+    fn face_count(rasterizer: &Rasterizer) -> usize {
+        rasterizer.font_system.db().faces().count()
+    }
+
+    #[test]
+    fn sent_fonts_are_loaded_once() {
+        let mut rasterizer = Rasterizer::default();
+        let faces = face_count(&rasterizer);
+        let data = font_data(0, notosans::REGULAR_TTF.len() + 1);
+
+        load(&mut rasterizer, data.clone()).unwrap();
+        load(&mut rasterizer, data.clone()).unwrap();
+        assert_eq!(face_count(&rasterizer), faces + 1);
+        assert_eq!(rasterizer.sent_fonts, HashSet::from([MediaRef::of(&data)]));
+
+        assert!(load(&mut rasterizer, vec![0; 16]).is_err());
+    }
+
+    #[test]
+    fn sent_fonts_are_forgotten_past_the_limit() {
+        let mut rasterizer = Rasterizer::default();
+        let faces = face_count(&rasterizer);
+        let len = MAX_SENT_FONTS_SIZE as usize / 3 + 1;
+
+        load(&mut rasterizer, font_data(0, len)).unwrap();
+        load(&mut rasterizer, font_data(1, len)).unwrap();
+        assert_eq!(face_count(&rasterizer), faces + 2);
+
+        // There is no room left for a third one, the previous ones are forgotten
+        let data = font_data(2, len);
+        load(&mut rasterizer, data.clone()).unwrap();
+        assert_eq!(face_count(&rasterizer), faces + 1);
+        assert_eq!(rasterizer.sent_fonts, HashSet::from([MediaRef::of(&data)]));
+
+        // Every font of a batch must fit at once
+        let fonts: Vec<_> = (3..6)
+            .map(|padding| {
+                text(ff::Font {
+                    family: DEFAULT_FONT_FAMILY.to_string(),
+                    data: Some(ff::Media::Inline(font_data(padding, len))),
+                })
+            })
+            .collect();
+        assert!(
+            rasterizer
+                .load_fonts(&fonts, &MediaCache::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn missing_families_fall_back_to_the_default_font() {
+        let rasterizer = Rasterizer::default();
+        let missing = "Surely Not An Installed Family";
+
+        assert_eq!(
+            rasterizer.resolve_family(missing, false).unwrap(),
+            DEFAULT_FONT_FAMILY
+        );
+        assert_eq!(
+            rasterizer
+                .resolve_family(DEFAULT_FONT_FAMILY, false)
+                .unwrap(),
+            DEFAULT_FONT_FAMILY
+        );
+        // The family was supposed to be in the font data sent along the overlay
+        assert!(rasterizer.resolve_family(missing, true).is_err());
+    }
+}
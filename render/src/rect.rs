@@ -0,0 +1,100 @@
+/// An axis-aligned rectangle, with the same CSSOM coordinate system as `Frame`.
+///
+/// Used to describe the area covered by an `Overlay` and the damaged regions of the canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    /// Horizontal offset from the left edge, can be negative.
+    pub left: i32,
+    /// Vertical offset from the top edge, can be negative.
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(left: i32, top: i32, width: u32, height: u32) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    /// Whether the rectangle covers no pixel at all.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // Edges are computed on i64 so that nothing can overflow
+
+    fn right(&self) -> i64 {
+        self.left as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.top as i64 + self.height as i64
+    }
+
+    /// Build a rectangle from its edges, which must fit within the `i32`/`u32` ranges.
+    fn from_edges(left: i64, top: i64, right: i64, bottom: i64) -> Self {
+        Self {
+            left: left as i32,
+            top: top as i32,
+            width: (right - left).max(0) as u32,
+            height: (bottom - top).max(0) as u32,
+        }
+    }
+
+    /// The area covered by both rectangles, `None` if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left = (self.left as i64).max(other.left as i64);
+        let top = (self.top as i64).max(other.top as i64);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        (left < right && top < bottom).then(|| Self::from_edges(left, top, right, bottom))
+    }
+
+    /// The rectangle grown by `margin` on every side, saturating at the edge of the coordinate space.
+    pub fn inflate(&self, margin: u32) -> Rect {
+        let margin = margin as i64;
+        let clamp = |edge: i64| edge.clamp(i32::MIN as i64, i32::MAX as i64);
+        let (left, top) = (
+            clamp(self.left as i64 - margin),
+            clamp(self.top as i64 - margin),
+        );
+        Self::from_edges(
+            left,
+            top,
+            (self.right() + margin).min(left + u32::MAX as i64),
+            (self.bottom() + margin).min(top + u32::MAX as i64),
+        )
+    }
+
+    /// The rectangle moved by (`dx`, `dy`), saturating at the edge of the coordinate space.
+    pub fn translate(&self, dx: i32, dy: i32) -> Rect {
+        Self {
+            left: self.left.saturating_add(dx),
+            top: self.top.saturating_add(dy),
+            ..*self
+        }
+    }
+
+    /// The smallest rectangle containing both rectangles.
+    pub fn bounding(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
+        let left = (self.left as i64).min(other.left as i64);
+        let top = (self.top as i64).min(other.top as i64);
+        // Saturates at the edge of the coordinate space rather than wrapping around
+        let right = self.right().max(other.right()).min(left + u32::MAX as i64);
+        let bottom = self.bottom().max(other.bottom()).min(top + u32::MAX as i64);
+        Self::from_edges(left, top, right, bottom)
+    }
+}
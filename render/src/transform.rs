@@ -0,0 +1,534 @@
+use ff::{Position, Size, Transform};
+use image::{
+    ImageBuffer, Rgba,
+    imageops::{self, FilterType},
+};
+
+use crate::frame::{Frame, mul_div255};
+
+/// Largest width or height of a transformed overlay, twice the width of a 4K screen.
+///
+/// Transforms are given by other players, this keeps a typo in a scale factor from eating all the memory.
+pub(crate) const MAX_DIMENSION: u32 = 8192;
+
+/// Where the anchor of an overlay ends up on a canvas of the given dimensions, in pixels.
+///
+/// Positions are kept within `MAX_DIMENSION` pixels of the canvas, overlays anchored further away
+/// could not reach it anyway, and the offsets computed from them cannot overflow.
+pub fn resolve_position(position: &Position, canvas: (u32, u32)) -> anyhow::Result<(i32, i32)> {
+    let (x, y) = match *position {
+        Position::Absolute { left, top } => (left as f64, top as f64),
+        Position::Relative { origin, left, top } => {
+            let x = (origin.x + left) as f64 * canvas.0 as f64;
+            let y = (origin.y + top) as f64 * canvas.1 as f64;
+            anyhow::ensure!(
+                x.is_finite() && y.is_finite(),
+                "the position must be made of finite numbers"
+            );
+            (x.round(), y.round())
+        }
+    };
+
+    let max = MAX_DIMENSION as f64;
+    Ok((
+        x.clamp(-max, canvas.0 as f64 + max) as i32,
+        y.clamp(-max, canvas.1 as f64 + max) as i32,
+    ))
+}
+
+/// Resample a `Frame` according to a `Transform`, its offsets being where the anchor of the transform ends up.
+/// Sizes relative to the canvas are resolved against its `canvas` dimensions.
+///
+/// This is done once when rasterizing, the resulting `Frame` is blitted as is.
+pub fn apply_transform(
+    frame: Frame,
+    transform: &Transform,
+    canvas: (u32, u32),
+) -> anyhow::Result<Frame> {
+    if *transform == Transform::default() {
+        return Ok(frame);
+    }
+
+    let (width, height) = target_size(frame.width, frame.height, &transform.size, canvas.1)?;
+    rotated_size(width, height, transform.rotation_deg)?;
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&transform.opacity),
+        "the opacity must be between 0 and 1"
+    );
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&transform.anchor.x) && (0.0..=1.0).contains(&transform.anchor.y),
+        "the anchor must be within the overlay, between 0 and 1"
+    );
+
+    let frame = resize(frame, width, height);
+    // Position of the anchor in the pixels of the resized frame, snapped to the pixel grid so that it stays sharp
+    let anchor = (
+        (transform.anchor.x * width as f32).round(),
+        (transform.anchor.y * height as f32).round(),
+    );
+
+    let mut frame = if transform.rotation_deg % 360.0 == 0.0 {
+        Frame {
+            offset_left: frame.offset_left.saturating_sub(anchor.0 as i32),
+            offset_top: frame.offset_top.saturating_sub(anchor.1 as i32),
+            ..frame
+        }
+    } else {
+        rotate(&frame, transform.rotation_deg, anchor)
+    };
+
+    if transform.opacity < 1.0 {
+        let opacity = (transform.opacity * 255.0).round() as u32;
+        for channel in &mut frame.buffer {
+            *channel = mul_div255(*channel as u32, opacity) as u8;
+        }
+    }
+
+    Ok(frame)
+}
+
+/// Size in pixels of a `width` x `height` frame once resized and rotated by `transform`, see `apply_transform`.
+pub(crate) fn transformed_size(
+    width: u32,
+    height: u32,
+    transform: &Transform,
+    canvas: (u32, u32),
+) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = target_size(width, height, &transform.size, canvas.1)?;
+    rotated_size(width, height, transform.rotation_deg)
+}
+
+/// Size in pixels of a `width` x `height` frame once resized, on a canvas `canvas_height` pixels high.
+fn target_size(
+    width: u32,
+    height: u32,
+    size: &Size,
+    canvas_height: u32,
+) -> anyhow::Result<(u32, u32)> {
+    let (native_width, native_height) = (width as f64, height as f64);
+    let (width, height) = match *size {
+        Size::Native
+        | Size::Pixels {
+            width: None,
+            height: None,
+        } => (native_width, native_height),
+        Size::Scale(factor) => {
+            anyhow::ensure!(
+                factor.is_finite() && factor >= 0.0,
+                "the scale factor must be a positive number"
+            );
+            (native_width * factor as f64, native_height * factor as f64)
+        }
+        Size::Pixels {
+            width: Some(width),
+            height: Some(height),
+        } => (width as f64, height as f64),
+        Size::Pixels {
+            width: Some(width),
+            height: None,
+        } => {
+            let height = native_height * width as f64 / native_width.max(1.0);
+            (width as f64, height)
+        }
+        Size::Pixels {
+            width: None,
+            height: Some(height),
+        } => {
+            let width = native_width * height as f64 / native_height.max(1.0);
+            (width, height as f64)
+        }
+        Size::CanvasHeight(fraction) => {
+            anyhow::ensure!(
+                fraction.is_finite() && fraction >= 0.0,
+                "the fraction of the canvas height must be a positive number"
+            );
+            let height = canvas_height as f64 * fraction as f64;
+            let width = native_width * height / native_height.max(1.0);
+            (width, height)
+        }
+    };
+
+    let max = MAX_DIMENSION as f64;
+    anyhow::ensure!(
+        width <= max && height <= max,
+        "an overlay cannot be larger than {MAX_DIMENSION}x{MAX_DIMENSION} pixels, got {width:.0}x{height:.0}"
+    );
+    Ok((width.round() as u32, height.round() as u32))
+}
+
+/// Size in pixels of the bounding box of a `width` x `height` frame rotated by `degrees`.
+fn rotated_size(width: u32, height: u32, degrees: f32) -> anyhow::Result<(u32, u32)> {
+    anyhow::ensure!(
+        degrees.is_finite(),
+        "the rotation must be a finite number of degrees"
+    );
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let (width, height) = (width as f64, height as f64);
+    // Rounded like in `rotate`, so that 90° does not grow the frame by a pixel
+    let round = |v: f64| ((v * 1000.0).round() / 1000.0).ceil();
+    let (width, height) = (
+        round(width * cos + height * sin),
+        round(width * sin + height * cos),
+    );
+
+    let max = MAX_DIMENSION as f64;
+    anyhow::ensure!(
+        width <= max && height <= max,
+        "a rotated overlay cannot be larger than {MAX_DIMENSION}x{MAX_DIMENSION} pixels, got {width:.0}x{height:.0}"
+    );
+    Ok((width as u32, height as u32))
+}
+
+/// Resample a frame to the given size, with a Lanczos filter.
+fn resize(frame: Frame, width: u32, height: u32) -> Frame {
+    if (frame.width, frame.height) == (width, height) {
+        return frame;
+    }
+    if frame.width == 0 || frame.height == 0 || width == 0 || height == 0 {
+        return Frame::new(
+            frame.offset_left,
+            frame.offset_top,
+            width,
+            height,
+            frame.delay_ms,
+        );
+    }
+
+    let image: ImageBuffer<Rgba<u8>, _> =
+        ImageBuffer::from_raw(frame.width, frame.height, frame.buffer)
+            .expect("frame buffer does not match its dimensions");
+    let mut buffer = imageops::resize(&image, width, height, FilterType::Lanczos3).into_raw();
+
+    // Lanczos overshoots around sharp edges, a premultiplied color cannot exceed its alpha
+    for px in buffer.chunks_exact_mut(4) {
+        let alpha = px[3];
+        for channel in &mut px[..3] {
+            *channel = (*channel).min(alpha);
+        }
+    }
+
+    Frame {
+        width,
+        height,
+        buffer,
+        ..frame
+    }
+}
+
+/// Rotate a frame clockwise around `anchor` (in pixels of the frame), which stays at the offsets of the frame.
+///
+/// The resulting frame is as large as the bounding box of the rotated frame.
+fn rotate(frame: &Frame, degrees: f32, anchor: (f32, f32)) -> Frame {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = (frame.width as f32, frame.height as f32);
+
+    // Corners relative to the anchor once rotated, rounded so that 90° does not grow the frame by a pixel
+    let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)].map(|(x, y)| {
+        let (x, y) = (x - anchor.0, y - anchor.1);
+        let round = |v: f32| (v * 1000.0).round() / 1000.0;
+        (round(x * cos - y * sin), round(x * sin + y * cos))
+    });
+    let left = corners
+        .iter()
+        .map(|c| c.0)
+        .fold(f32::INFINITY, f32::min)
+        .floor();
+    let top = corners
+        .iter()
+        .map(|c| c.1)
+        .fold(f32::INFINITY, f32::min)
+        .floor();
+    let right = corners
+        .iter()
+        .map(|c| c.0)
+        .fold(f32::NEG_INFINITY, f32::max)
+        .ceil();
+    let bottom = corners
+        .iter()
+        .map(|c| c.1)
+        .fold(f32::NEG_INFINITY, f32::max)
+        .ceil();
+
+    let (rotated_width, rotated_height) = ((right - left) as u32, (bottom - top) as u32);
+    let mut buffer = vec![0; rotated_width as usize * rotated_height as usize * 4];
+
+    for (i, px) in buffer.chunks_exact_mut(4).enumerate() {
+        // Center of the pixel relative to the anchor, rotated back into the source frame
+        let x = left + (i % rotated_width as usize) as f32 + 0.5;
+        let y = top + (i / rotated_width as usize) as f32 + 0.5;
+        let src_x = x * cos + y * sin + anchor.0;
+        let src_y = -x * sin + y * cos + anchor.1;
+
+        px.copy_from_slice(&sample_bilinear(frame, src_x - 0.5, src_y - 0.5));
+    }
+
+    Frame {
+        offset_left: frame.offset_left.saturating_add(left as i32),
+        offset_top: frame.offset_top.saturating_add(top as i32),
+        width: rotated_width,
+        height: rotated_height,
+        buffer,
+        delay_ms: frame.delay_ms,
+    }
+}
+
+/// Bilinear interpolation of the pixels around (`x`, `y`), pixels outside of the frame being transparent.
+fn sample_bilinear(frame: &Frame, x: f32, y: f32) -> [u8; 4] {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let pixel = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= frame.width as i64 || y >= frame.height as i64 {
+            return [0.0; 4];
+        }
+        let i = (y as usize * frame.width as usize + x as usize) * 4;
+        let px = &frame.buffer[i..i + 4];
+        [px[0] as f32, px[1] as f32, px[2] as f32, px[3] as f32]
+    };
+
+    let taps = [
+        (pixel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (pixel(x0 + 1, y0), fx * (1.0 - fy)),
+        (pixel(x0, y0 + 1), (1.0 - fx) * fy),
+        (pixel(x0 + 1, y0 + 1), fx * fy),
+    ];
+
+    let mut out = [0; 4];
+    for (channel, value) in out.iter_mut().enumerate() {
+        let sum: f32 = taps.iter().map(|(px, weight)| px[channel] * weight).sum();
+        *value = sum.round().clamp(0.0, 255.0) as u8;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Anchor;
+
+    use crate::rect::Rect;
+
+    use super::*;
+
+    const CANVAS: (u32, u32) = (1920, 1080);
+
+    /// A 2x1 frame at (10, 20), red then blue.
+    fn red_blue() -> Frame {
+        Frame::from_bytes(10, 20, 2, 1, &[255, 0, 0, 255, 0, 0, 255, 255], 0)
+    }
+
+    #[test]
+    fn identity_leaves_frame_untouched() {
+        let frame = apply_transform(red_blue(), &Transform::default(), CANVAS).unwrap();
+        assert_eq!((frame.offset_left, frame.offset_top), (10, 20));
+        assert_eq!(frame.buffer, red_blue().buffer);
+    }
+
+    #[test]
+    fn target_sizes() {
+        let size = |size| target_size(400, 200, &size, 1080).unwrap();
+        assert_eq!(size(Size::Native), (400, 200));
+        assert_eq!(size(Size::Scale(0.5)), (200, 100));
+        assert_eq!(
+            size(Size::Pixels {
+                width: Some(100),
+                height: None
+            }),
+            (100, 50)
+        );
+        assert_eq!(
+            size(Size::Pixels {
+                width: None,
+                height: Some(100)
+            }),
+            (200, 100)
+        );
+        assert_eq!(
+            size(Size::Pixels {
+                width: Some(10),
+                height: Some(10)
+            }),
+            (10, 10)
+        );
+
+        assert_eq!(size(Size::CanvasHeight(0.5)), (1080, 540));
+
+        assert!(target_size(400, 200, &Size::Scale(f32::NAN), 1080).is_err());
+        assert!(target_size(400, 200, &Size::Scale(-1.0), 1080).is_err());
+        assert!(target_size(400, 200, &Size::Scale(1000.0), 1080).is_err());
+        assert!(target_size(400, 200, &Size::CanvasHeight(-0.5), 1080).is_err());
+    }
+
+    #[test]
+    fn rotated_sizes() {
+        assert_eq!(rotated_size(400, 200, 0.0).unwrap(), (400, 200));
+        assert_eq!(rotated_size(400, 200, 90.0).unwrap(), (200, 400));
+        assert_eq!(rotated_size(100, 100, 45.0).unwrap(), (142, 142));
+        assert!(rotated_size(400, 200, f32::INFINITY).is_err());
+
+        // Fits unrotated, but not its diagonal
+        let transform = Transform {
+            size: Size::Pixels {
+                width: Some(MAX_DIMENSION),
+                height: Some(MAX_DIMENSION),
+            },
+            rotation_deg: 45.0,
+            ..Transform::default()
+        };
+        assert!(transformed_size(10, 10, &transform, CANVAS).is_err());
+        assert!(apply_transform(red_blue(), &transform, CANVAS).is_err());
+    }
+
+    #[test]
+    fn relative_positions() {
+        let relative = |origin, left, top| {
+            resolve_position(&Position::Relative { origin, left, top }, CANVAS).unwrap()
+        };
+        assert_eq!(relative(Anchor::TOP_LEFT, 0.0, 0.0), (0, 0));
+        assert_eq!(relative(Anchor::CENTER, 0.0, 0.0), (960, 540));
+        assert_eq!(relative(Anchor::BOTTOM_RIGHT, -0.1, -0.5), (1728, 540));
+        assert_eq!(relative(Anchor::TOP_LEFT, -1.0, 2.0), (-1920, 2160));
+        assert!(
+            resolve_position(
+                &Position::Relative {
+                    origin: Anchor::TOP_LEFT,
+                    left: f32::NAN,
+                    top: 0.0
+                },
+                CANVAS
+            )
+            .is_err()
+        );
+
+        let absolute = Position::Absolute { left: -5, top: 7 };
+        assert_eq!(resolve_position(&absolute, CANVAS).unwrap(), (-5, 7));
+    }
+
+    #[test]
+    fn far_positions_are_kept_near_the_canvas() {
+        let max = MAX_DIMENSION as i32;
+        let far = Position::Relative {
+            origin: Anchor::TOP_LEFT,
+            left: 1e12,
+            top: -1e12,
+        };
+        let (left, top) = resolve_position(&far, CANVAS).unwrap();
+        assert_eq!((left, top), (CANVAS.0 as i32 + max, -max));
+
+        let absolute = Position::Absolute {
+            left: i32::MIN,
+            top: i32::MAX,
+        };
+        assert_eq!(
+            resolve_position(&absolute, CANVAS).unwrap(),
+            (-max, CANVAS.1 as i32 + max)
+        );
+
+        // Still out of the canvas once transformed
+        let transform = Transform {
+            size: Size::Scale(2.0),
+            anchor: Anchor::BOTTOM_RIGHT,
+            rotation_deg: 30.0,
+            ..Transform::default()
+        };
+        let frame = Frame::from_bytes(left, top, 2, 1, &red_blue().buffer, 0);
+        let frame = apply_transform(frame, &transform, CANVAS).unwrap();
+        let canvas = Rect::new(0, 0, CANVAS.0, CANVAS.1);
+        assert!(frame.bounds().intersection(&canvas).is_none());
+    }
+
+    #[test]
+    fn anchor_is_placed_at_offsets() {
+        let transform = Transform {
+            size: Size::Scale(2.0),
+            anchor: Anchor::BOTTOM_RIGHT,
+            ..Transform::default()
+        };
+        let frame = apply_transform(red_blue(), &transform, CANVAS).unwrap();
+        assert_eq!((frame.width, frame.height), (4, 2));
+        assert_eq!((frame.offset_left, frame.offset_top), (6, 18));
+    }
+
+    #[test]
+    fn anchors_are_within_the_overlay() {
+        let outside = Transform {
+            anchor: Anchor { x: 1e9, y: 0.0 },
+            ..Transform::default()
+        };
+        assert!(apply_transform(red_blue(), &outside, CANVAS).is_err());
+
+        // Far away overlays stay there rather than wrapping around
+        let far = Frame::from_bytes(i32::MIN, i32::MAX, 2, 1, &red_blue().buffer, 0);
+        for rotation_deg in [0.0, 45.0] {
+            let transform = Transform {
+                anchor: Anchor::BOTTOM_RIGHT,
+                rotation_deg,
+                ..Transform::default()
+            };
+            let frame = apply_transform(far.clone(), &transform, CANVAS).unwrap();
+            assert_eq!(frame.offset_left, i32::MIN);
+            assert!(frame.offset_top > i32::MAX - 4);
+        }
+    }
+
+    #[test]
+    fn rotate_quarter_turn() {
+        let transform = Transform {
+            rotation_deg: 90.0,
+            ..Transform::default()
+        };
+        let frame = apply_transform(red_blue(), &transform, CANVAS).unwrap();
+
+        // Around the top-left corner, the frame now hangs below and to the left of it
+        assert_eq!((frame.width, frame.height), (1, 2));
+        assert_eq!((frame.offset_left, frame.offset_top), (9, 20));
+        assert_eq!(frame.buffer, [255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn rotate_half_turn_around_center() {
+        let transform = Transform {
+            rotation_deg: 180.0,
+            anchor: Anchor::CENTER,
+            ..Transform::default()
+        };
+        let frame = apply_transform(red_blue(), &transform, CANVAS).unwrap();
+
+        assert_eq!((frame.width, frame.height), (2, 1));
+        assert_eq!((frame.offset_left, frame.offset_top), (9, 20));
+        assert_eq!(frame.buffer, [0, 0, 255, 255, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn opacity_scales_premultiplied_pixels() {
+        let transform = Transform {
+            opacity: 0.5,
+            ..Transform::default()
+        };
+        let frame = apply_transform(red_blue(), &transform, CANVAS).unwrap();
+        assert_eq!(frame.buffer, [128, 0, 0, 128, 0, 0, 128, 128]);
+
+        let invalid = Transform {
+            opacity: 2.0,
+            ..Transform::default()
+        };
+        assert!(apply_transform(red_blue(), &invalid, CANVAS).is_err());
+    }
+
+    #[test]
+    fn resize_keeps_colors_within_alpha() {
+        // Sharp transparent to opaque edges make Lanczos overshoot
+        let pixels = [[0, 0, 0, 0], [255, 255, 255, 255]].repeat(8).concat();
+        let frame = Frame::from_bytes(0, 0, 16, 1, &pixels, 0);
+        let transform = Transform {
+            size: Size::Scale(3.3),
+            ..Transform::default()
+        };
+
+        let frame = apply_transform(frame, &transform, CANVAS).unwrap();
+        for px in frame.buffer.chunks_exact(4) {
+            assert!(px[..3].iter().all(|&c| c <= px[3]), "{px:?}");
+        }
+    }
+}
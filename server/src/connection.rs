@@ -0,0 +1,80 @@
+use ff::{ClientMessage, Codec};
+use futures_util::{SinkExt, StreamExt};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{Error, Message, error::ProtocolError, protocol::WebSocketConfig},
+};
+use uuid::Uuid;
+
+use crate::state::{OUTGOING_QUEUE_SIZE, SharedState};
+
+/// Serve a single client from the websocket handshake until it disconnects.
+///
+/// Outgoing messages are written by a dedicated task so that the client can be sent messages
+/// (e.g. an overlay relayed from another member) while we are waiting on its next message.
+pub async fn handle_connection(
+    stream: TcpStream,
+    state: SharedState,
+    codec: Codec,
+) -> anyhow::Result<()> {
+    let config = WebSocketConfig::default().max_message_size(Some(codec.max_frame_size()));
+    let websocket = tokio_tungstenite::accept_async_with_config(stream, Some(config)).await?;
+    let (mut sink, mut stream) = websocket.split();
+
+    let (outgoing_tx, mut outgoing_rx) = mpsc::channel(OUTGOING_QUEUE_SIZE);
+    let client_id = state.lock().unwrap().connect(outgoing_tx);
+    println!("Client {client_id} connected");
+
+    let writer = tokio::spawn(async move {
+        while let Some(bytes) = outgoing_rx.recv().await {
+            sink.send(Message::Binary(bytes)).await?;
+        }
+        sink.close().await
+    });
+
+    let result = read_messages(client_id, &mut stream, &state, codec).await;
+
+    // Disconnecting drops the outgoing queue, which lets the writer flush and stop.
+    // It is dropped sooner if the client does not keep up, the writer then closes the websocket.
+    state.lock().unwrap().disconnect(client_id);
+    let _ = writer.await;
+    println!("Client {client_id} disconnected");
+
+    result
+}
+
+/// Handle every message received from the client until the websocket closes.
+async fn read_messages(
+    client_id: Uuid,
+    stream: &mut futures_util::stream::SplitStream<WebSocketStream<TcpStream>>,
+    state: &SharedState,
+    codec: Codec,
+) -> anyhow::Result<()> {
+    while let Some(message) = stream.next().await {
+        let message = match message {
+            Ok(message) => message,
+            // Clients going away without a proper close frame is not worth reporting
+            Err(Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        match message {
+            Message::Binary(bytes) => {
+                let message = codec.decode::<ClientMessage>(&bytes);
+                if !state.lock().unwrap().receive(client_id, message) {
+                    break;
+                }
+            }
+            Message::Text(_) => state.lock().unwrap().send_error(
+                client_id,
+                "Messages must be sent as MessagePack binary frames".to_string(),
+            ),
+            Message::Close(_) => break,
+            // Pings are answered by tungstenite itself
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+        }
+    }
+
+    Ok(())
+}
@@ -1,3 +1,47 @@
-fn main() {
-    println!("Hello, world!");
+use std::{
+    env,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use ff::Codec;
+use tokio::net::TcpListener;
+
+use crate::state::ServerState;
+
+mod connection;
+mod media;
+mod party;
+mod state;
+
+/// Address the relay listens on when none is given as the first argument.
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9001";
+
+/// Websocket relay between command-centers and splash-screens.
+///
+/// Usage : `friendlyfire-server [ADDRESS]`
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let address: SocketAddr = env::args()
+        .nth(1)
+        .as_deref()
+        .unwrap_or(DEFAULT_ADDRESS)
+        .parse()?;
+
+    let listener = TcpListener::bind(address).await?;
+    println!("Listening on ws://{address}");
+
+    let codec = Codec::default();
+    let state = Arc::new(Mutex::new(ServerState::new(codec)));
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = connection::handle_connection(stream, state, codec).await {
+                eprintln!("Connection with {peer} closed: {err}");
+            }
+        });
+    }
 }
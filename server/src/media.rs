@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ff::{IncomingTransfer, MAX_CONCURRENT_UPLOADS, MediaCache, MediaRef, Sha256Digest};
+use uuid::Uuid;
+
+use crate::state::RequestError;
+
+/// Biggest media that can be uploaded, in bytes.
+const MAX_MEDIA_SIZE: u64 = 256 * 1024 * 1024;
+
+/// How long the upload of a disconnected client is kept, waiting for it to come back and resume it.
+const ABANDONED_UPLOAD_EXPIRY: Duration = Duration::from_secs(10 * 60);
+
+/// Media uploaded in chunks by command-centers, see `ff::OutgoingTransfer`.
+#[derive(Default)]
+pub struct MediaStore {
+    /// Uploads that are still going on, indexed by the media being uploaded.
+    /// They outlive their uploader for a while, so that they can be resumed after a reconnection.
+    uploads: HashMap<MediaRef, Upload>,
+
+    /// Uploads that are done, shared by every party so that the same media is only uploaded once.
+    cache: MediaCache,
+}
+
+/// A media being uploaded.
+struct Upload {
+    transfer_id: Uuid,
+    /// Client sending the chunks, the last one to announce the media.
+    uploader: Uuid,
+    /// When the uploader disconnected, the upload expires after `ABANDONED_UPLOAD_EXPIRY`.
+    abandoned_at: Option<Instant>,
+    transfer: IncomingTransfer,
+}
+
+impl MediaStore {
+    /// Start or resume an upload, which `uploader` takes over if someone else started it.
+    ///
+    /// Returns the offset the upload should resume from, or `None` if the media is already known.
+    pub fn begin_upload(
+        &mut self,
+        uploader: Uuid,
+        transfer_id: Uuid,
+        total_size: u64,
+        sha256: Sha256Digest,
+    ) -> Result<Option<u64>, RequestError> {
+        self.expire_uploads(Instant::now());
+
+        let media = MediaRef {
+            sha256,
+            len: total_size,
+        };
+        if self.cache.contains(&media) {
+            return Ok(None);
+        }
+
+        if total_size > MAX_MEDIA_SIZE {
+            return Err(RequestError::MediaTooLarge);
+        }
+
+        let ongoing = self
+            .uploads
+            .iter()
+            .filter(|(other, upload)| **other != media && upload.uploader == uploader)
+            .count();
+        if ongoing >= MAX_CONCURRENT_UPLOADS {
+            return Err(RequestError::TooManyUploads);
+        }
+
+        let upload = self.uploads.entry(media).or_insert_with(|| Upload {
+            transfer_id,
+            uploader,
+            abandoned_at: None,
+            transfer: IncomingTransfer::new(total_size, sha256),
+        });
+        upload.transfer_id = transfer_id;
+        upload.uploader = uploader;
+        upload.abandoned_at = None;
+
+        // Empty media do not need any chunk
+        if upload.transfer.is_complete() {
+            self.complete_upload(media)?;
+            return Ok(None);
+        }
+
+        Ok(Some(upload.transfer.offset()))
+    }
+
+    /// Append a chunk to an upload of `uploader`.
+    ///
+    /// Returns whether the upload is now complete, in which case the media becomes available.
+    pub fn push_chunk(
+        &mut self,
+        uploader: Uuid,
+        transfer_id: Uuid,
+        index: u32,
+        bytes: &[u8],
+    ) -> Result<bool, RequestError> {
+        let (media, upload) = self
+            .uploads
+            .iter_mut()
+            .find(|(_, upload)| upload.transfer_id == transfer_id && upload.uploader == uploader)
+            .ok_or(RequestError::UnknownTransfer)?;
+        upload
+            .transfer
+            .push(index, bytes)
+            .map_err(RequestError::Transfer)?;
+
+        if !upload.transfer.is_complete() {
+            return Ok(false);
+        }
+
+        let media = *media;
+        self.complete_upload(media)?;
+        Ok(true)
+    }
+
+    /// Keep the uploads of a client that disconnected for a while, in case it comes back to resume them.
+    pub fn abandon_uploads(&mut self, uploader: Uuid) {
+        let now = Instant::now();
+        for upload in self.uploads.values_mut() {
+            if upload.uploader == uploader {
+                upload.abandoned_at = Some(now);
+            }
+        }
+        self.expire_uploads(now);
+    }
+
+    /// Fully uploaded media.
+    pub fn get(&self, media: &MediaRef) -> Option<&Arc<Vec<u8>>> {
+        self.cache.get(media)
+    }
+
+    /// Media that were not uploaded yet.
+    pub fn missing(&self, media: &[MediaRef]) -> Vec<MediaRef> {
+        self.cache.missing(media)
+    }
+
+    /// Drop the uploads that were abandoned for too long.
+    fn expire_uploads(&mut self, now: Instant) {
+        self.uploads.retain(|_, upload| {
+            upload.abandoned_at.is_none_or(|abandoned_at| {
+                now.duration_since(abandoned_at) < ABANDONED_UPLOAD_EXPIRY
+            })
+        });
+    }
+
+    /// Check the digest of a complete upload, it has to start over if it does not match.
+    fn complete_upload(&mut self, media: MediaRef) -> Result<(), RequestError> {
+        let upload = self.uploads.remove(&media).unwrap();
+        let data = upload.transfer.finish().map_err(RequestError::Transfer)?;
+
+        self.cache.insert(media, Arc::new(data));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::CHUNK_SIZE;
+
+    use super::*;
+
+    /// Media of two chunks, along with its reference.
+    fn media() -> (MediaRef, Vec<u8>) {
+        let data = vec![7; CHUNK_SIZE + 1];
+        (MediaRef::of(&data), data)
+    }
+
+    fn begin(store: &mut MediaStore, uploader: Uuid, media: &MediaRef) -> Option<u64> {
+        store
+            .begin_upload(uploader, media.transfer_id(), media.len, media.sha256)
+            .unwrap()
+    }
+
+    #[test]
+    fn uploads_resume_after_a_reconnection() {
+        let mut store = MediaStore::default();
+        let (media, data) = media();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert_eq!(begin(&mut store, first, &media), Some(0));
+        let chunk = &data[..CHUNK_SIZE];
+        assert!(
+            !store
+                .push_chunk(first, media.transfer_id(), 0, chunk)
+                .unwrap()
+        );
+        store.abandon_uploads(first);
+
+        assert_eq!(begin(&mut store, second, &media), Some(CHUNK_SIZE as u64));
+        let last = &data[CHUNK_SIZE..];
+        assert_eq!(
+            store.push_chunk(first, media.transfer_id(), 1, last),
+            Err(RequestError::UnknownTransfer)
+        );
+        assert!(
+            store
+                .push_chunk(second, media.transfer_id(), 1, last)
+                .unwrap()
+        );
+        assert_eq!(store.get(&media).map(|data| data.len()), Some(data.len()));
+        assert_eq!(begin(&mut store, second, &media), None);
+    }
+
+    #[test]
+    fn abandoned_uploads_expire() {
+        let mut store = MediaStore::default();
+        let (media, data) = media();
+        let uploader = Uuid::new_v4();
+
+        begin(&mut store, uploader, &media);
+        store
+            .push_chunk(uploader, media.transfer_id(), 0, &data[..CHUNK_SIZE])
+            .unwrap();
+        store.abandon_uploads(uploader);
+        store.expire_uploads(Instant::now() + ABANDONED_UPLOAD_EXPIRY);
+
+        assert!(store.uploads.is_empty());
+        assert_eq!(begin(&mut store, uploader, &media), Some(0));
+    }
+
+    #[test]
+    fn uploads_are_limited_per_client() {
+        let mut store = MediaStore::default();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let medias: Vec<_> = (0..=MAX_CONCURRENT_UPLOADS as u8)
+            .map(|byte| MediaRef::of(&[byte; CHUNK_SIZE + 1]))
+            .collect();
+        let (last, ongoing) = medias.split_last().unwrap();
+
+        for media in ongoing {
+            assert_eq!(begin(&mut store, first, media), Some(0));
+        }
+        assert_eq!(
+            store.begin_upload(first, last.transfer_id(), last.len, last.sha256),
+            Err(RequestError::TooManyUploads)
+        );
+        // Resuming an ongoing upload does not count as another one
+        assert_eq!(begin(&mut store, first, &ongoing[0]), Some(0));
+        assert_eq!(begin(&mut store, second, last), Some(0));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+/// Privileges of a member inside a `Party`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Member that created the party, can create invitation links and disband the party.
+    Creator,
+    /// Can only send and receive overlays.
+    Member,
+}
+
+impl Role {
+    /// Whether this role allows managing the party (invitations, disbanding), only its creator can.
+    pub fn can_manage(self) -> bool {
+        self == Role::Creator
+    }
+}
+
+/// A group of clients exchanging overlays, much like a Discord server.
+pub struct Party {
+    /// Members of the party, indexed by their server-assigned id.
+    pub members: HashMap<Uuid, Role>,
+
+    /// Overlays currently going through the acknowledgement process, if any.
+    /// Only one batch can be in flight at a time in a given party.
+    pub batch: Option<Batch>,
+}
+
+impl Party {
+    /// Create a party whose only member is its creator.
+    pub fn new(creator: Uuid) -> Self {
+        Self {
+            members: HashMap::from([(creator, Role::Creator)]),
+            batch: None,
+        }
+    }
+}
+
+/// Overlays broadcasted to a party that are waiting to be fired.
+///
+/// See the "Sending Overlays" sequence diagram in the README.
+pub struct Batch {
+    /// Client that sent the overlays, the only one allowed to fire them.
+    pub sender: Uuid,
+
+    /// Members the overlays were relayed to.
+    pub recipients: HashSet<Uuid>,
+
+    /// Recipients that did not send `ClientMessageType::OverlaysAck` yet.
+    pending_download: HashSet<Uuid>,
+
+    /// Recipients that did not send `ClientMessageType::RasterizationAck` yet.
+    pending_rasterization: HashSet<Uuid>,
+
+    /// Whether `ServerMessageType::OverlaysFullAck` was already emitted.
+    downloaded: bool,
+
+    /// Whether `ServerMessageType::RasterizationFullAck` was already emitted.
+    rasterized: bool,
+}
+
+/// Progress of a `Batch` that must be reported to its sender.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchProgress {
+    /// Every recipient downloaded the overlays.
+    Downloaded,
+    /// Every recipient rasterized the overlays.
+    Rasterized,
+}
+
+impl Batch {
+    pub fn new(sender: Uuid, recipients: HashSet<Uuid>) -> Self {
+        Self {
+            sender,
+            pending_download: recipients.clone(),
+            pending_rasterization: recipients.clone(),
+            recipients,
+            downloaded: false,
+            rasterized: false,
+        }
+    }
+
+    pub fn ack_download(&mut self, member: Uuid) {
+        self.pending_download.remove(&member);
+    }
+
+    pub fn ack_rasterization(&mut self, member: Uuid) {
+        self.pending_rasterization.remove(&member);
+    }
+
+    /// Stop waiting on a recipient, e.g. because it left the party.
+    pub fn remove_recipient(&mut self, member: Uuid) {
+        self.recipients.remove(&member);
+        self.pending_download.remove(&member);
+        self.pending_rasterization.remove(&member);
+    }
+
+    /// Whether the batch can be fired, i.e. every recipient rasterized the overlays.
+    pub fn is_ready(&self) -> bool {
+        self.downloaded && self.rasterized
+    }
+
+    /// Returns the steps reached since the last call, in order.
+    ///
+    /// Each step is only ever returned once, and rasterization is never reported before download.
+    pub fn poll_progress(&mut self) -> Vec<BatchProgress> {
+        let mut progress = Vec::new();
+
+        if !self.downloaded && self.pending_download.is_empty() {
+            self.downloaded = true;
+            progress.push(BatchProgress::Downloaded);
+        }
+
+        if self.downloaded && !self.rasterized && self.pending_rasterization.is_empty() {
+            self.rasterized = true;
+            progress.push(BatchProgress::Rasterized);
+        }
+
+        progress
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_is_reported_once_and_in_order() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut batch = Batch::new(Uuid::new_v4(), HashSet::from([first, second]));
+
+        // Rasterization is only reported once everything was downloaded
+        batch.ack_download(first);
+        batch.ack_rasterization(first);
+        batch.ack_rasterization(second);
+        assert!(batch.poll_progress().is_empty());
+        assert!(!batch.is_ready());
+
+        batch.ack_download(second);
+        assert_eq!(
+            batch.poll_progress(),
+            [BatchProgress::Downloaded, BatchProgress::Rasterized]
+        );
+        assert!(batch.poll_progress().is_empty());
+        assert!(batch.is_ready());
+    }
+
+    #[test]
+    fn removed_recipients_are_not_waited_on() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut batch = Batch::new(Uuid::new_v4(), HashSet::from([first, second]));
+
+        batch.ack_download(first);
+        batch.remove_recipient(second);
+        assert_eq!(batch.poll_progress(), [BatchProgress::Downloaded]);
+
+        batch.ack_rasterization(first);
+        assert_eq!(batch.poll_progress(), [BatchProgress::Rasterized]);
+        assert_eq!(batch.recipients, HashSet::from([first]));
+    }
+
+    #[test]
+    fn batches_without_recipients_are_ready_right_away() {
+        let mut batch = Batch::new(Uuid::new_v4(), HashSet::new());
+        assert_eq!(
+            batch.poll_progress(),
+            [BatchProgress::Downloaded, BatchProgress::Rasterized]
+        );
+        assert!(batch.is_ready());
+    }
+}
@@ -0,0 +1,1083 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use ff::{
+    ClientKind, ClientMessage, ClientMessageType, Codec, CodecError, DisplayOptions, Media,
+    MediaRef, OutgoingTransfer, Overlay, SenderInfo, ServerMessage, ServerMessageType,
+    Sha256Digest, TransferError, Version,
+};
+use tokio::sync::mpsc::{Sender, error::TrySendError};
+use tokio_tungstenite::tungstenite::Bytes;
+use uuid::Uuid;
+
+use crate::{
+    media::MediaStore,
+    party::{Batch, BatchProgress, Party, Role},
+};
+
+/// Identifier used as `SenderInfo.id` for messages emitted by the server itself (errors, aggregated acks...).
+pub const SERVER_ID: Uuid = Uuid::nil();
+
+/// Prefix of every invitation link handed out by the server.
+const INVITATION_LINK_PREFIX: &str = "friendlyfire://join/";
+
+/// Messages queued for a client before it is considered too slow and disconnected.
+pub const OUTGOING_QUEUE_SIZE: usize = 256;
+
+/// `ServerState` shared between every connection task.
+pub type SharedState = Arc<Mutex<ServerState>>;
+
+/// A client connected to the server.
+struct Client {
+    /// Queue of encoded `ServerMessage` to be written on the client's websocket.
+    /// Dropped once full, which closes the connection of a client that does not keep up.
+    outgoing: Option<Sender<Bytes>>,
+
+    /// Kind of client, known once it said `ClientMessageType::Hello`.
+    kind: Option<ClientKind>,
+
+    /// Party the client is currently part of.
+    party: Option<Uuid>,
+
+    /// Media being sent to the client, indexed by transfer id.
+    downloads: HashMap<Uuid, OutgoingTransfer>,
+}
+
+/// Reasons for the server to refuse a client request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestError {
+    /// Every message must be preceded by `ClientMessageType::Hello`.
+    NotGreeted,
+    /// `ClientMessageType::Hello` can only be sent once.
+    AlreadyGreeted,
+    /// The request requires being part of a party.
+    NotInParty,
+    /// Clients can only be part of one party at a time.
+    AlreadyInParty,
+    /// The given party does not exist, or the client is not part of it.
+    UnknownParty,
+    /// The invitation link does not lead to any party.
+    UnknownInvitation,
+    /// The client role does not allow this request.
+    PermissionDenied,
+    /// Another batch of overlays is still waiting to be fired.
+    BatchInProgress,
+    /// A batch of overlays must contain at least one overlay.
+    EmptyBatch,
+    /// There is no batch of overlays sent by this client.
+    NoBatch,
+    /// Some recipients did not rasterize the overlays yet.
+    BatchNotReady,
+    /// The transfer does not exist, or is already complete.
+    UnknownTransfer,
+    /// Media are limited in size, even when sent in chunks.
+    MediaTooLarge,
+    /// Clients can only upload `ff::MAX_CONCURRENT_UPLOADS` media at once.
+    TooManyUploads,
+    /// An overlay references media that were not fully uploaded.
+    MissingMedia,
+    /// The chunked transfer sub-protocol was not respected.
+    Transfer(TransferError),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            RequestError::NotGreeted => "You must say Hello first",
+            RequestError::AlreadyGreeted => "You already said Hello",
+            RequestError::NotInParty => "You are not part of any party",
+            RequestError::AlreadyInParty => "You are already part of a party",
+            RequestError::UnknownParty => "This party does not exist or you are not part of it",
+            RequestError::UnknownInvitation => "This invitation link is invalid",
+            RequestError::PermissionDenied => "You do not have the permission to do that",
+            RequestError::BatchInProgress => "Another batch of overlays is waiting to be fired",
+            RequestError::EmptyBatch => "A batch must contain at least one overlay",
+            RequestError::NoBatch => "You have no batch of overlays waiting to be fired",
+            RequestError::BatchNotReady => "Not every member has rasterized the overlays yet",
+            RequestError::UnknownTransfer => "This transfer does not exist",
+            RequestError::MediaTooLarge => "This media is too large",
+            RequestError::TooManyUploads => "You are already uploading too many media",
+            RequestError::MissingMedia => "Some media were not uploaded",
+            RequestError::Transfer(err) => return write!(f, "Transfer failed: {err}"),
+        };
+        message.fmt(f)
+    }
+}
+
+/// Every party and connected client known by the relay server.
+pub struct ServerState {
+    codec: Codec,
+    clients: HashMap<Uuid, Client>,
+    parties: HashMap<Uuid, Party>,
+    /// Invitation links, and the party they give access to.
+    invitations: HashMap<String, Uuid>,
+    media: MediaStore,
+}
+
+impl ServerState {
+    pub fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            clients: HashMap::new(),
+            parties: HashMap::new(),
+            invitations: HashMap::new(),
+            media: MediaStore::default(),
+        }
+    }
+
+    /// Register a newly connected client and return its server-assigned id.
+    pub fn connect(&mut self, outgoing: Sender<Bytes>) -> Uuid {
+        let id = Uuid::new_v4();
+        self.clients.insert(
+            id,
+            Client {
+                outgoing: Some(outgoing),
+                kind: None,
+                party: None,
+                downloads: HashMap::new(),
+            },
+        );
+        id
+    }
+
+    /// Forget about a client, removing it from its party.
+    ///
+    /// Dropping the client also closes its outgoing queue.
+    pub fn disconnect(&mut self, id: Uuid) {
+        self.leave_party(id);
+        self.media.abandon_uploads(id);
+        self.clients.remove(&id);
+    }
+
+    /// Handle a message decoded from a client, after checking that it may talk with us.
+    ///
+    /// Clients must first say `ClientMessageType::Hello` with a compatible version, otherwise they are rejected.
+    /// Returns `false` once the client was rejected, its connection should then be closed.
+    pub fn receive(&mut self, from: Uuid, message: Result<ClientMessage, CodecError>) -> bool {
+        let greeted = self.client_mut(from).kind.is_some();
+
+        let reason = match message {
+            Ok(ClientMessage {
+                version,
+                kind: ClientMessageType::Hello { .. },
+            }) if !version.is_compatible_with(&Version::CURRENT) => format!(
+                "version {version} is not compatible with the server version {}",
+                Version::CURRENT
+            ),
+            Ok(message) if greeted || matches!(message.kind, ClientMessageType::Hello { .. }) => {
+                self.handle(from, message.kind);
+                return true;
+            }
+            Ok(_) => RequestError::NotGreeted.to_string(),
+            Err(err @ CodecError::IncompatibleVersion(_)) => err.to_string(),
+            Err(err) => {
+                self.send_error(from, format!("Invalid message: {err}"));
+                return true;
+            }
+        };
+
+        self.reject(from, reason);
+        false
+    }
+
+    /// Handle a message received from a client, answering with an error if it gets refused.
+    pub fn handle(&mut self, from: Uuid, message: ClientMessageType) {
+        let greeted = self.client_mut(from).kind.is_some();
+
+        let result = match message {
+            ClientMessageType::Hello { kind } => self.greet(from, kind),
+            _ if !greeted => Err(RequestError::NotGreeted),
+            ClientMessageType::CreateParty => self.create_party(from),
+            ClientMessageType::DisbandParty { party_id } => self.disband_party(from, party_id),
+            ClientMessageType::JoinParty { invitation_link } => {
+                self.join_party(from, &invitation_link)
+            }
+            ClientMessageType::CreateInvationLink { party_id } => {
+                self.create_invitation_link(from, party_id)
+            }
+            ClientMessageType::Overlays { overlays, options } => {
+                self.broadcast_overlays(from, overlays, options)
+            }
+            ClientMessageType::HaveMedia { media } => {
+                let media = self.media.missing(&media);
+                self.send(&[from], SERVER_ID, ServerMessageType::WantMedia { media });
+                Ok(())
+            }
+            ClientMessageType::WantMedia { media } => self.begin_downloads(from, &media),
+            ClientMessageType::BeginTransfer {
+                transfer_id,
+                total_size,
+                sha256,
+            } => self.begin_upload(from, transfer_id, total_size, sha256),
+            ClientMessageType::TransferChunk {
+                transfer_id,
+                index,
+                bytes,
+            } => self.receive_chunk(from, transfer_id, index, &bytes),
+            ClientMessageType::ResumeTransfer {
+                transfer_id,
+                offset,
+            } => self.resume_download(from, transfer_id, offset),
+            ClientMessageType::ChunkAck { transfer_id, index } => {
+                self.ack_chunk(from, transfer_id, index)
+            }
+            ClientMessageType::TransferComplete { transfer_id } => {
+                self.client_mut(from).downloads.remove(&transfer_id);
+                Ok(())
+            }
+            ClientMessageType::OverlaysAck => self.ack_overlays(from),
+            ClientMessageType::RasterizationAck => self.ack_rasterization(from),
+            ClientMessageType::RasterizationFailed { reason } => {
+                self.fail_rasterization(from, reason)
+            }
+            ClientMessageType::Fire => self.fire(from),
+            ClientMessageType::Error { message } => {
+                eprintln!("Client {from} reported an error: {message}");
+                Ok(())
+            }
+        };
+
+        if let Err(err) = result {
+            self.send_error(from, err.to_string());
+        }
+    }
+
+    /// Send an `Error` message emitted by the server to a client.
+    pub fn send_error(&mut self, to: Uuid, message: String) {
+        self.send(&[to], SERVER_ID, ServerMessageType::Error { message });
+    }
+
+    /// Refuse to talk with a client, the connection should be closed afterwards.
+    pub fn reject(&mut self, to: Uuid, reason: String) {
+        self.send(&[to], SERVER_ID, ServerMessageType::Rejected { reason });
+    }
+
+    /// The version itself was already checked when receiving the message, see `receive`.
+    fn greet(&mut self, from: Uuid, kind: ClientKind) -> Result<(), RequestError> {
+        let client = self.client_mut(from);
+        if client.kind.is_some() {
+            return Err(RequestError::AlreadyGreeted);
+        }
+        client.kind = Some(kind);
+
+        self.send(
+            &[from],
+            SERVER_ID,
+            ServerMessageType::Welcome { client_id: from },
+        );
+        Ok(())
+    }
+
+    fn create_party(&mut self, from: Uuid) -> Result<(), RequestError> {
+        let client = self.client_mut(from);
+        if client.party.is_some() {
+            return Err(RequestError::AlreadyInParty);
+        }
+
+        let party_id = Uuid::new_v4();
+        client.party = Some(party_id);
+        self.parties.insert(party_id, Party::new(from));
+
+        self.send(&[from], from, ServerMessageType::PartyCreated { party_id });
+        Ok(())
+    }
+
+    fn create_invitation_link(&mut self, from: Uuid, party_id: Uuid) -> Result<(), RequestError> {
+        let party = self.party_of(from, party_id)?;
+        if !party.members[&from].can_manage() {
+            return Err(RequestError::PermissionDenied);
+        }
+
+        let invitation_link = format!("{INVITATION_LINK_PREFIX}{}", Uuid::new_v4().simple());
+        self.invitations.insert(invitation_link.clone(), party_id);
+
+        self.send(
+            &[from],
+            from,
+            ServerMessageType::InvitationLinkCreated {
+                party_id,
+                invitation_link,
+            },
+        );
+        Ok(())
+    }
+
+    fn join_party(&mut self, from: Uuid, invitation_link: &str) -> Result<(), RequestError> {
+        let party_id = *self
+            .invitations
+            .get(invitation_link.trim())
+            .ok_or(RequestError::UnknownInvitation)?;
+
+        let client = self.client_mut(from);
+        if client.party.is_some() {
+            return Err(RequestError::AlreadyInParty);
+        }
+        client.party = Some(party_id);
+
+        // Invitations are removed along with their party, so the party always exists
+        let party = self.parties.get_mut(&party_id).unwrap();
+        party.members.insert(from, Role::Member);
+
+        self.send(&[from], from, ServerMessageType::JoinAccepted { party_id });
+        Ok(())
+    }
+
+    fn disband_party(&mut self, from: Uuid, party_id: Uuid) -> Result<(), RequestError> {
+        let party = self.party_of(from, party_id)?;
+        if !party.members[&from].can_manage() {
+            return Err(RequestError::PermissionDenied);
+        }
+
+        self.remove_party(party_id, from);
+        Ok(())
+    }
+
+    /// Delete a party and its invitations, letting its members know that they are not part of it anymore.
+    fn remove_party(&mut self, party_id: Uuid, by: Uuid) {
+        let Some(party) = self.parties.remove(&party_id) else {
+            return;
+        };
+        self.invitations.retain(|_, id| *id != party_id);
+
+        let members: Vec<Uuid> = party.members.into_keys().collect();
+        for member in &members {
+            if let Some(client) = self.clients.get_mut(member) {
+                client.party = None;
+            }
+        }
+
+        self.send(&members, by, ServerMessageType::PartyDisbanded { party_id });
+    }
+
+    /// Relay a batch of overlays to every splash-screen of the sender's party.
+    ///
+    /// Referenced media must have been uploaded beforehand, see `ClientMessageType::HaveMedia`.
+    fn broadcast_overlays(
+        &mut self,
+        from: Uuid,
+        overlays: Vec<Overlay>,
+        options: DisplayOptions,
+    ) -> Result<(), RequestError> {
+        if overlays.is_empty() {
+            return Err(RequestError::EmptyBatch);
+        }
+
+        let media: Vec<MediaRef> = overlays
+            .iter()
+            .filter_map(|overlay| match overlay.media() {
+                Some(Media::Ref(media)) => Some(*media),
+                _ => None,
+            })
+            .collect();
+        if !self.media.missing(&media).is_empty() {
+            return Err(RequestError::MissingMedia);
+        }
+
+        let party_id = self.current_party(from)?;
+        let party = self.parties.get_mut(&party_id).unwrap();
+        if party.batch.is_some() {
+            return Err(RequestError::BatchInProgress);
+        }
+
+        let recipients: HashSet<Uuid> = party
+            .members
+            .keys()
+            .copied()
+            .filter(|member| *member != from)
+            .filter(|member| self.clients[member].kind == Some(ClientKind::SplashScreen))
+            .collect();
+        party.batch = Some(Batch::new(from, recipients.clone()));
+
+        // Splash-screens ask for the media they do not have cached before acknowledging the overlays
+        let recipients: Vec<Uuid> = recipients.into_iter().collect();
+        if !media.is_empty() {
+            self.send(
+                &recipients,
+                SERVER_ID,
+                ServerMessageType::HaveMedia { media },
+            );
+        }
+        self.send(
+            &recipients,
+            from,
+            ServerMessageType::Overlays { overlays, options },
+        );
+
+        // With nobody to wait on, the batch is immediately ready
+        self.report_progress(party_id);
+        Ok(())
+    }
+
+    fn begin_upload(
+        &mut self,
+        from: Uuid,
+        transfer_id: Uuid,
+        total_size: u64,
+        sha256: Sha256Digest,
+    ) -> Result<(), RequestError> {
+        // Media are only ever needed by the overlays sent to a party
+        self.current_party(from)?;
+
+        let reply = match self
+            .media
+            .begin_upload(from, transfer_id, total_size, sha256)?
+        {
+            Some(offset) => ServerMessageType::ResumeTransfer {
+                transfer_id,
+                offset,
+            },
+            None => ServerMessageType::TransferComplete { transfer_id },
+        };
+
+        self.send(&[from], SERVER_ID, reply);
+        Ok(())
+    }
+
+    fn receive_chunk(
+        &mut self,
+        from: Uuid,
+        transfer_id: Uuid,
+        index: u32,
+        bytes: &[u8],
+    ) -> Result<(), RequestError> {
+        let complete = self.media.push_chunk(from, transfer_id, index, bytes)?;
+
+        self.send(
+            &[from],
+            SERVER_ID,
+            ServerMessageType::ChunkAck { transfer_id, index },
+        );
+        if complete {
+            self.send(
+                &[from],
+                SERVER_ID,
+                ServerMessageType::TransferComplete { transfer_id },
+            );
+        }
+        Ok(())
+    }
+
+    /// Announce uploaded media to a client, chunks are sent once it tells us where to start from.
+    fn begin_downloads(&mut self, to: Uuid, media: &[MediaRef]) -> Result<(), RequestError> {
+        let blobs = media
+            .iter()
+            .map(|media| self.media.get(media).cloned().map(|data| (media, data)))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(RequestError::MissingMedia)?;
+
+        for (media, data) in blobs {
+            let transfer_id = media.transfer_id();
+            let transfer = OutgoingTransfer::with_digest(transfer_id, data, media.sha256);
+
+            self.client_mut(to).downloads.insert(transfer_id, transfer);
+            self.send(
+                &[to],
+                SERVER_ID,
+                ServerMessageType::BeginTransfer {
+                    transfer_id,
+                    total_size: media.len,
+                    sha256: media.sha256,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Start sending media to a client, from the offset it reached before a reconnection if any.
+    fn resume_download(
+        &mut self,
+        from: Uuid,
+        transfer_id: Uuid,
+        offset: u64,
+    ) -> Result<(), RequestError> {
+        self.client_mut(from)
+            .downloads
+            .get_mut(&transfer_id)
+            .ok_or(RequestError::UnknownTransfer)?
+            .resume_from(offset)
+            .map_err(RequestError::Transfer)?;
+
+        self.send_chunks(from);
+        Ok(())
+    }
+
+    fn ack_chunk(&mut self, from: Uuid, transfer_id: Uuid, index: u32) -> Result<(), RequestError> {
+        self.client_mut(from)
+            .downloads
+            .get_mut(&transfer_id)
+            .ok_or(RequestError::UnknownTransfer)?
+            .ack(index);
+
+        self.send_chunks(from);
+        Ok(())
+    }
+
+    /// Send as many chunks of the downloads of a client as allowed by their windows.
+    ///
+    /// Half of its queue is kept for other messages, chunks held back are sent as the client acknowledges others.
+    /// At least one chunk goes through, so that there is always an acknowledgement to come.
+    fn send_chunks(&mut self, to: Uuid) {
+        let client = self.client_mut(to);
+        let Some(outgoing) = &client.outgoing else {
+            return;
+        };
+        let room = outgoing
+            .capacity()
+            .saturating_sub(OUTGOING_QUEUE_SIZE / 2)
+            .max(1);
+
+        let mut chunks = Vec::new();
+        for (&transfer_id, transfer) in &mut client.downloads {
+            while chunks.len() < room {
+                let Some((index, bytes)) = transfer.next_chunk() else {
+                    break;
+                };
+                chunks.push(ServerMessageType::TransferChunk {
+                    transfer_id,
+                    index,
+                    bytes: bytes.to_vec(),
+                });
+            }
+        }
+
+        for chunk in chunks {
+            self.send(&[to], SERVER_ID, chunk);
+        }
+    }
+
+    fn ack_overlays(&mut self, from: Uuid) -> Result<(), RequestError> {
+        let party_id = self.current_party(from)?;
+        let batch = self.parties.get_mut(&party_id).unwrap().batch.as_mut();
+        batch.ok_or(RequestError::NoBatch)?.ack_download(from);
+
+        self.report_progress(party_id);
+        Ok(())
+    }
+
+    fn ack_rasterization(&mut self, from: Uuid) -> Result<(), RequestError> {
+        let party_id = self.current_party(from)?;
+        let batch = self.parties.get_mut(&party_id).unwrap().batch.as_mut();
+        batch.ok_or(RequestError::NoBatch)?.ack_rasterization(from);
+
+        self.report_progress(party_id);
+        Ok(())
+    }
+
+    /// Leave a recipient out of the batch, so that the others can still be fired.
+    fn fail_rasterization(&mut self, from: Uuid, reason: String) -> Result<(), RequestError> {
+        let party_id = self.current_party(from)?;
+        let batch = self.parties.get_mut(&party_id).unwrap().batch.as_mut();
+        let batch = batch
+            .filter(|batch| batch.recipients.contains(&from))
+            .ok_or(RequestError::NoBatch)?;
+        batch.remove_recipient(from);
+
+        let sender = batch.sender;
+        self.send(
+            &[sender],
+            from,
+            ServerMessageType::RasterizationFailed { reason },
+        );
+        self.report_progress(party_id);
+        Ok(())
+    }
+
+    fn fire(&mut self, from: Uuid) -> Result<(), RequestError> {
+        let party_id = self.current_party(from)?;
+        let party = self.parties.get_mut(&party_id).unwrap();
+
+        match &party.batch {
+            Some(batch) if batch.sender == from => {
+                if !batch.is_ready() {
+                    return Err(RequestError::BatchNotReady);
+                }
+            }
+            _ => return Err(RequestError::NoBatch),
+        }
+
+        let batch = party.batch.take().unwrap();
+        let recipients: Vec<Uuid> = batch.recipients.into_iter().collect();
+        self.send(&recipients, from, ServerMessageType::Fire);
+        Ok(())
+    }
+
+    /// Let the sender of the party's batch know about any progress made by its recipients.
+    fn report_progress(&mut self, party_id: Uuid) {
+        let Some(batch) = self
+            .parties
+            .get_mut(&party_id)
+            .and_then(|party| party.batch.as_mut())
+        else {
+            return;
+        };
+
+        let sender = batch.sender;
+        for progress in batch.poll_progress() {
+            let kind = match progress {
+                BatchProgress::Downloaded => ServerMessageType::OverlaysFullAck,
+                BatchProgress::Rasterized => ServerMessageType::RasterizationFullAck,
+            };
+            self.send(&[sender], SERVER_ID, kind);
+        }
+    }
+
+    /// Remove a client from its party.
+    ///
+    /// The party is disbanded once its creator leaves, as nobody else could manage it.
+    fn leave_party(&mut self, id: Uuid) {
+        let Some(party_id) = self.clients.get_mut(&id).and_then(|c| c.party.take()) else {
+            return;
+        };
+        let Some(party) = self.parties.get_mut(&party_id) else {
+            return;
+        };
+
+        if party.members.remove(&id).is_some_and(Role::can_manage) {
+            self.remove_party(party_id, id);
+            return;
+        }
+
+        // Nobody is left to fire the batch
+        if party.batch.as_ref().is_some_and(|batch| batch.sender == id) {
+            party.batch = None;
+        } else if let Some(batch) = &mut party.batch {
+            batch.remove_recipient(id);
+        }
+
+        self.report_progress(party_id);
+    }
+
+    /// Requests are only ever handled for connected clients.
+    fn client_mut(&mut self, id: Uuid) -> &mut Client {
+        self.clients
+            .get_mut(&id)
+            .expect("requests are handled while the client is connected")
+    }
+
+    /// Id of the party the client is part of.
+    fn current_party(&self, id: Uuid) -> Result<Uuid, RequestError> {
+        self.clients
+            .get(&id)
+            .and_then(|client| client.party)
+            .ok_or(RequestError::NotInParty)
+    }
+
+    /// Party with the given id, as long as the client is part of it.
+    fn party_of(&self, id: Uuid, party_id: Uuid) -> Result<&Party, RequestError> {
+        self.parties
+            .get(&party_id)
+            .filter(|party| party.members.contains_key(&id))
+            .ok_or(RequestError::UnknownParty)
+    }
+
+    /// Encode a `ServerMessage` once and queue it for every given client.
+    fn send(&mut self, to: &[Uuid], sender: Uuid, kind: ServerMessageType) {
+        let message = ServerMessage {
+            version: Version::CURRENT,
+            sender: SenderInfo { id: sender },
+            kind,
+        };
+
+        let bytes = match self.codec.encode(&message) {
+            Ok(bytes) => Bytes::from(bytes),
+            Err(err) => {
+                eprintln!("Failed to encode {message:?}: {err}");
+                return;
+            }
+        };
+
+        for id in to {
+            let Some(client) = self.clients.get_mut(id) else {
+                continue;
+            };
+            let Some(outgoing) = &client.outgoing else {
+                continue;
+            };
+            // The receiving end only closes once the client is disconnecting
+            if let Err(TrySendError::Full(_)) = outgoing.try_send(bytes.clone()) {
+                eprintln!("Client {id} does not keep up with its messages, disconnecting it");
+                client.outgoing = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::{Media, Transform};
+    use tokio::sync::mpsc::{self, Receiver};
+
+    use super::*;
+
+    /// A connected client, whose messages are read straight from its outgoing queue.
+    struct TestClient {
+        id: Uuid,
+        outgoing: Receiver<Bytes>,
+    }
+
+    impl TestClient {
+        /// Messages received since the last call.
+        fn received(&mut self) -> Vec<ServerMessageType> {
+            let mut received = Vec::new();
+            while let Ok(bytes) = self.outgoing.try_recv() {
+                let message: ServerMessage = Codec::default().decode(&bytes).unwrap();
+                received.push(message.kind);
+            }
+            received
+        }
+    }
+
+    fn connect(state: &mut ServerState, kind: ClientKind) -> TestClient {
+        let (outgoing_tx, outgoing) = mpsc::channel(OUTGOING_QUEUE_SIZE);
+        let id = state.connect(outgoing_tx);
+        assert!(state.receive(id, Ok(hello(Version::CURRENT, kind))));
+
+        let mut client = TestClient { id, outgoing };
+        assert!(matches!(
+            client.received()[..],
+            [ServerMessageType::Welcome { .. }]
+        ));
+        client
+    }
+
+    /// A party created by a command-center, along with an invitation link to it.
+    fn create_party(state: &mut ServerState) -> (TestClient, Uuid, String) {
+        let mut creator = connect(state, ClientKind::CommandCenter);
+        state.handle(creator.id, ClientMessageType::CreateParty);
+        let [ServerMessageType::PartyCreated { party_id }] = creator.received()[..] else {
+            panic!("the party was not created");
+        };
+
+        state.handle(
+            creator.id,
+            ClientMessageType::CreateInvationLink { party_id },
+        );
+        let Some(ServerMessageType::InvitationLinkCreated {
+            invitation_link, ..
+        }) = creator.received().pop()
+        else {
+            panic!("no invitation link was created");
+        };
+        (creator, party_id, invitation_link)
+    }
+
+    fn join(state: &mut ServerState, kind: ClientKind, invitation_link: &str) -> TestClient {
+        let mut member = connect(state, kind);
+        state.handle(
+            member.id,
+            ClientMessageType::JoinParty {
+                invitation_link: invitation_link.to_string(),
+            },
+        );
+        assert!(matches!(
+            member.received()[..],
+            [ServerMessageType::JoinAccepted { .. }]
+        ));
+        member
+    }
+
+    fn hello(version: Version, kind: ClientKind) -> ClientMessage {
+        ClientMessage {
+            version,
+            kind: ClientMessageType::Hello { kind },
+        }
+    }
+
+    fn overlays() -> ClientMessageType {
+        ClientMessageType::Overlays {
+            overlays: vec![Overlay::Image {
+                media: Media::Inline(Vec::new()),
+                offset_left: 0,
+                offset_top: 0,
+                z_index: 0,
+                start_ms: 0,
+                duration_ms: None,
+                transform: Transform::default(),
+                position: None,
+            }],
+            options: DisplayOptions { timeout_ms: 1000 },
+        }
+    }
+
+    fn is_error(received: &[ServerMessageType], error: RequestError) -> bool {
+        matches!(received, [ServerMessageType::Error { message }] if *message == error.to_string())
+    }
+
+    #[test]
+    fn only_the_creator_manages_the_party() {
+        let mut state = ServerState::new(Codec::default());
+        let (mut creator, party_id, invitation_link) = create_party(&mut state);
+        let mut member = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+
+        state.handle(
+            member.id,
+            ClientMessageType::CreateInvationLink { party_id },
+        );
+        assert!(is_error(&member.received(), RequestError::PermissionDenied));
+        state.handle(member.id, ClientMessageType::DisbandParty { party_id });
+        assert!(is_error(&member.received(), RequestError::PermissionDenied));
+
+        state.handle(creator.id, ClientMessageType::DisbandParty { party_id });
+        for client in [&mut creator, &mut member] {
+            assert!(matches!(
+                client.received()[..],
+                [ServerMessageType::PartyDisbanded { party_id: id }] if id == party_id
+            ));
+        }
+        assert!(state.parties.is_empty() && state.invitations.is_empty());
+
+        // Members are free to create their own party
+        state.handle(member.id, ClientMessageType::CreateParty);
+        assert!(matches!(
+            member.received()[..],
+            [ServerMessageType::PartyCreated { .. }]
+        ));
+    }
+
+    #[test]
+    fn parties_are_joined_once_with_a_valid_link() {
+        let mut state = ServerState::new(Codec::default());
+        let (_creator, _, invitation_link) = create_party(&mut state);
+
+        let mut stranger = connect(&mut state, ClientKind::SplashScreen);
+        state.handle(
+            stranger.id,
+            ClientMessageType::JoinParty {
+                invitation_link: "friendlyfire://join/nope".to_string(),
+            },
+        );
+        assert!(is_error(
+            &stranger.received(),
+            RequestError::UnknownInvitation
+        ));
+
+        let mut member = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+        state.handle(member.id, ClientMessageType::JoinParty { invitation_link });
+        assert!(is_error(&member.received(), RequestError::AlreadyInParty));
+        state.handle(member.id, ClientMessageType::CreateParty);
+        assert!(is_error(&member.received(), RequestError::AlreadyInParty));
+    }
+
+    #[test]
+    fn overlays_are_relayed_to_the_splash_screens_of_the_party() {
+        let mut state = ServerState::new(Codec::default());
+        let (mut creator, _, invitation_link) = create_party(&mut state);
+        let mut splash_screens = [
+            join(&mut state, ClientKind::SplashScreen, &invitation_link),
+            join(&mut state, ClientKind::SplashScreen, &invitation_link),
+        ];
+        let mut command_center = join(&mut state, ClientKind::CommandCenter, &invitation_link);
+        let (_, _, other_link) = create_party(&mut state);
+        let mut outsider = join(&mut state, ClientKind::SplashScreen, &other_link);
+
+        state.handle(creator.id, overlays());
+
+        for splash_screen in &mut splash_screens {
+            assert!(matches!(
+                splash_screen.received()[..],
+                [ServerMessageType::Overlays { .. }]
+            ));
+        }
+        assert!(command_center.received().is_empty());
+        assert!(outsider.received().is_empty());
+        assert!(creator.received().is_empty());
+    }
+
+    #[test]
+    fn leaving_members_are_forgotten() {
+        let mut state = ServerState::new(Codec::default());
+        let (mut creator, party_id, invitation_link) = create_party(&mut state);
+        let member = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+        let mut other = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+
+        state.disconnect(member.id);
+        assert!(!state.clients.contains_key(&member.id));
+        assert!(!state.parties[&party_id].members.contains_key(&member.id));
+
+        // Nobody would be left to manage the party
+        state.disconnect(creator.id);
+        assert!(creator.received().is_empty());
+        assert!(matches!(
+            other.received()[..],
+            [ServerMessageType::PartyDisbanded { party_id: id }] if id == party_id
+        ));
+        assert!(state.parties.is_empty() && state.invitations.is_empty());
+
+        state.handle(other.id, overlays());
+        assert!(is_error(&other.received(), RequestError::NotInParty));
+    }
+
+    #[test]
+    fn batches_are_fired_once_every_splash_screen_is_ready() {
+        let mut state = ServerState::new(Codec::default());
+        let (mut creator, _, invitation_link) = create_party(&mut state);
+        let mut ready = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+        let mut failing = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+        let mut command_center = join(&mut state, ClientKind::CommandCenter, &invitation_link);
+
+        state.handle(creator.id, overlays());
+        ready.received();
+        failing.received();
+        state.handle(creator.id, ClientMessageType::Fire);
+        assert!(is_error(&creator.received(), RequestError::BatchNotReady));
+
+        state.handle(ready.id, ClientMessageType::OverlaysAck);
+        assert!(creator.received().is_empty());
+        state.handle(failing.id, ClientMessageType::OverlaysAck);
+        assert!(matches!(
+            creator.received()[..],
+            [ServerMessageType::OverlaysFullAck]
+        ));
+
+        state.handle(ready.id, ClientMessageType::RasterizationAck);
+        state.handle(creator.id, ClientMessageType::Fire);
+        assert!(is_error(&creator.received(), RequestError::BatchNotReady));
+
+        // The splash-screen that failed is left out, the others can still be fired
+        state.handle(
+            failing.id,
+            ClientMessageType::RasterizationFailed {
+                reason: "no memory left".to_string(),
+            },
+        );
+        assert!(matches!(
+            &creator.received()[..],
+            [
+                ServerMessageType::RasterizationFailed { reason },
+                ServerMessageType::RasterizationFullAck,
+            ] if reason == "no memory left"
+        ));
+
+        // Only the sender fires its batch
+        state.handle(command_center.id, ClientMessageType::Fire);
+        assert!(is_error(&command_center.received(), RequestError::NoBatch));
+
+        state.handle(creator.id, ClientMessageType::Fire);
+        assert!(matches!(ready.received()[..], [ServerMessageType::Fire]));
+        assert!(failing.received().is_empty());
+
+        state.handle(creator.id, ClientMessageType::Fire);
+        assert!(is_error(&creator.received(), RequestError::NoBatch));
+    }
+
+    #[test]
+    fn one_batch_at_a_time() {
+        let mut state = ServerState::new(Codec::default());
+        let (mut creator, _, invitation_link) = create_party(&mut state);
+        let mut splash_screen = join(&mut state, ClientKind::SplashScreen, &invitation_link);
+
+        state.handle(creator.id, overlays());
+        state.handle(creator.id, overlays());
+        assert!(is_error(&creator.received(), RequestError::BatchInProgress));
+
+        // Failures only come from recipients of the batch
+        state.handle(
+            creator.id,
+            ClientMessageType::RasterizationFailed {
+                reason: String::new(),
+            },
+        );
+        assert!(is_error(&creator.received(), RequestError::NoBatch));
+
+        // A recipient leaving does not hold the batch back
+        splash_screen.received();
+        state.disconnect(splash_screen.id);
+        assert!(matches!(
+            creator.received()[..],
+            [
+                ServerMessageType::OverlaysFullAck,
+                ServerMessageType::RasterizationFullAck
+            ]
+        ));
+    }
+
+    #[test]
+    fn clients_must_say_hello_first() {
+        let mut state = ServerState::new(Codec::default());
+        let (outgoing_tx, outgoing) = mpsc::channel(OUTGOING_QUEUE_SIZE);
+        let mut client = TestClient {
+            id: state.connect(outgoing_tx),
+            outgoing,
+        };
+
+        let create_party = ClientMessage {
+            version: Version::CURRENT,
+            kind: ClientMessageType::CreateParty,
+        };
+        assert!(!state.receive(client.id, Ok(create_party)));
+        assert!(matches!(
+            client.received()[..],
+            [ServerMessageType::Rejected { .. }]
+        ));
+        assert!(state.parties.is_empty());
+    }
+
+    #[test]
+    fn incompatible_versions_are_rejected() {
+        let mut state = ServerState::new(Codec::default());
+        let (outgoing_tx, outgoing) = mpsc::channel(OUTGOING_QUEUE_SIZE);
+        let mut client = TestClient {
+            id: state.connect(outgoing_tx),
+            outgoing,
+        };
+        let incompatible = Version::new(u8::MAX, 0, 0);
+
+        assert!(!state.receive(client.id, Ok(hello(incompatible, ClientKind::SplashScreen))));
+        assert!(matches!(
+            client.received()[..],
+            [ServerMessageType::Rejected { .. }]
+        ));
+        assert_eq!(state.clients[&client.id].kind, None);
+
+        // Messages the codec could not make sense of because of their version
+        let err = CodecError::IncompatibleVersion(incompatible);
+        assert!(!state.receive(client.id, Err(err)));
+        assert!(matches!(
+            client.received()[..],
+            [ServerMessageType::Rejected { .. }]
+        ));
+
+        // Other decoding errors are only reported
+        assert!(state.receive(client.id, Err(CodecError::Truncated)));
+        assert!(matches!(
+            client.received()[..],
+            [ServerMessageType::Error { .. }]
+        ));
+    }
+
+    #[test]
+    fn uploads_require_a_party() {
+        let mut state = ServerState::new(Codec::default());
+        let mut client = connect(&mut state, ClientKind::CommandCenter);
+        let media = MediaRef::of(b"media");
+
+        state.handle(
+            client.id,
+            ClientMessageType::BeginTransfer {
+                transfer_id: media.transfer_id(),
+                total_size: media.len,
+                sha256: media.sha256,
+            },
+        );
+        assert!(is_error(&client.received(), RequestError::NotInParty));
+    }
+
+    #[test]
+    fn slow_clients_are_disconnected() {
+        let mut state = ServerState::new(Codec::default());
+        let mut client = connect(&mut state, ClientKind::CommandCenter);
+
+        for _ in 0..=OUTGOING_QUEUE_SIZE {
+            state.handle(
+                client.id,
+                ClientMessageType::HaveMedia { media: Vec::new() },
+            );
+        }
+        assert!(state.clients[&client.id].outgoing.is_none());
+        assert_eq!(client.received().len(), OUTGOING_QUEUE_SIZE);
+        assert!(client.outgoing.is_closed());
+    }
+}
@@ -0,0 +1,310 @@
+use std::{fmt, io};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned, de::IgnoredAny};
+
+use crate::{ClientMessage, ServerMessage, Version};
+
+/// Default upper bound of an encoded message size, in bytes.
+///
+/// Overlays are sent inline, so this must leave room for a few big animated images.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Messages that can go through a `Codec`.
+///
+/// Both have a `version` field at the top-level, which the codec relies on.
+pub trait WireMessage: Serialize + DeserializeOwned {}
+
+impl WireMessage for ClientMessage {}
+impl WireMessage for ServerMessage {}
+
+/// Byte format shared by every component of friendlyfire.
+///
+/// A frame holds exactly one MessagePack-encoded `ClientMessage` or `ServerMessage`,
+/// the framing itself is left to the transport (a websocket binary message).
+///
+/// Structs are encoded as maps, so that fields can be added in a backward compatible way.
+/// Compatibility of versions is checked by the handshake (`ClientMessageType::Hello`), not by the codec,
+/// so that an incompatible peer can still understand why it gets `ServerMessageType::Rejected`.
+#[derive(Clone, Copy, Debug)]
+pub struct Codec {
+    /// Frames bigger than this are refused, both when encoding and decoding.
+    max_frame_size: usize,
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+/// Only used to peek at the version of a message before decoding the whole of it.
+#[derive(Deserialize)]
+struct Envelope<V> {
+    version: Option<V>,
+}
+
+impl Codec {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self { max_frame_size }
+    }
+
+    /// Maximum size of a frame in bytes.
+    /// Transports should use it as their own message size limit.
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Encode a message into a single frame.
+    pub fn encode<M: WireMessage>(&self, message: &M) -> Result<Vec<u8>, CodecError> {
+        let frame =
+            rmp_serde::to_vec_named(message).map_err(|err| CodecError::Encode(err.to_string()))?;
+        self.check_size(frame.len())?;
+        Ok(frame)
+    }
+
+    /// Decode a message from a single frame.
+    ///
+    /// Messages from an incompatible version are decoded as long as they can be understood.
+    pub fn decode<M: WireMessage>(&self, frame: &[u8]) -> Result<M, CodecError> {
+        self.check_size(frame.len())?;
+
+        let envelope: Envelope<IgnoredAny> =
+            rmp_serde::from_slice(frame).map_err(CodecError::from_decode)?;
+        if envelope.version.is_none() {
+            return Err(CodecError::BadVersion);
+        }
+        let version = rmp_serde::from_slice::<Envelope<Version>>(frame)
+            .ok()
+            .and_then(|envelope| envelope.version)
+            .ok_or(CodecError::BadVersion)?;
+
+        rmp_serde::from_slice(frame).map_err(|err| {
+            // A message from an incompatible version fails in a way that does not help understand what went wrong
+            if version.is_compatible_with(&Version::CURRENT) {
+                CodecError::from_decode(err)
+            } else {
+                CodecError::IncompatibleVersion(version)
+            }
+        })
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), CodecError> {
+        if size > self.max_frame_size {
+            return Err(CodecError::Oversized {
+                size,
+                max: self.max_frame_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Errors that can occur while encoding or decoding a frame.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// The frame is bigger than the maximum frame size of the codec.
+    Oversized { size: usize, max: usize },
+    /// The frame ended before the end of the message.
+    Truncated,
+    /// The message type is unknown, likely because it comes from a newer version of the protocol.
+    UnknownVariant(String),
+    /// The version of the message is missing or malformed.
+    BadVersion,
+    /// The message comes from a version of the protocol that is not compatible with ours, and could not be decoded.
+    IncompatibleVersion(Version),
+    /// The frame is not a valid message.
+    Malformed(String),
+    /// The message could not be encoded.
+    Encode(String),
+}
+
+impl CodecError {
+    fn from_decode(err: rmp_serde::decode::Error) -> Self {
+        use rmp_serde::decode::Error;
+
+        match err {
+            Error::InvalidMarkerRead(err) | Error::InvalidDataRead(err)
+                if err.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                CodecError::Truncated
+            }
+            // serde only gives us a message for unknown variants, e.g.
+            // "unknown variant `Foo`, expected one of `Bar`, `Baz`"
+            Error::Syntax(message) if message.starts_with("unknown variant") => {
+                let variant = message.split('`').nth(1).unwrap_or_default();
+                CodecError::UnknownVariant(variant.to_string())
+            }
+            err => CodecError::Malformed(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::Oversized { size, max } => {
+                write!(
+                    f,
+                    "frame of {size} bytes exceeds the maximum of {max} bytes"
+                )
+            }
+            CodecError::Truncated => write!(f, "frame is truncated"),
+            CodecError::UnknownVariant(variant) => write!(f, "unknown message type `{variant}`"),
+            CodecError::BadVersion => write!(f, "message version is missing or malformed"),
+            CodecError::IncompatibleVersion(version) => write!(
+                f,
+                "version {version} is not compatible with version {}",
+                Version::CURRENT
+            ),
+            CodecError::Malformed(reason) => write!(f, "malformed message: {reason}"),
+            CodecError::Encode(reason) => write!(f, "could not encode message: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{SenderInfo, ServerMessageType};
+
+    /// A version that can never be compatible with `Version::CURRENT`.
+    const OTHER_VERSION: Version = Version::new(u8::MAX, 0, 0);
+
+    /// A server message of the given type, which may not exist in this version.
+    #[derive(Serialize)]
+    struct RawMessage {
+        version: Version,
+        sender: SenderInfo,
+        #[serde(rename = "type")]
+        kind: &'static str,
+    }
+
+    fn raw_frame(version: Version, kind: &'static str) -> Vec<u8> {
+        let message = RawMessage {
+            version,
+            sender: SenderInfo { id: Uuid::nil() },
+            kind,
+        };
+        rmp_serde::to_vec_named(&message).unwrap()
+    }
+
+    fn fire() -> ServerMessage {
+        ServerMessage {
+            version: Version::CURRENT,
+            sender: SenderInfo { id: Uuid::nil() },
+            kind: ServerMessageType::Fire,
+        }
+    }
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        let codec = Codec::default();
+        let frame = codec.encode(&fire()).unwrap();
+
+        let message: ServerMessage = codec.decode(&frame).unwrap();
+        assert_eq!(message.version, Version::CURRENT);
+        assert!(matches!(message.kind, ServerMessageType::Fire));
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let frame = Codec::default().encode(&fire()).unwrap();
+        let codec = Codec::new(frame.len() - 1);
+
+        let oversized = CodecError::Oversized {
+            size: frame.len(),
+            max: frame.len() - 1,
+        };
+        assert_eq!(codec.encode(&fire()).unwrap_err(), oversized);
+        assert_eq!(
+            codec.decode::<ServerMessage>(&frame).unwrap_err(),
+            oversized
+        );
+    }
+
+    #[test]
+    fn truncated_frames_are_reported() {
+        let codec = Codec::default();
+        let frame = codec.encode(&fire()).unwrap();
+
+        assert_eq!(
+            codec
+                .decode::<ServerMessage>(&frame[..frame.len() - 2])
+                .unwrap_err(),
+            CodecError::Truncated
+        );
+    }
+
+    #[test]
+    fn unknown_message_types_are_named() {
+        let codec = Codec::default();
+        assert_eq!(
+            codec
+                .decode::<ServerMessage>(&raw_frame(Version::CURRENT, "Teleport"))
+                .unwrap_err(),
+            CodecError::UnknownVariant("Teleport".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_or_malformed_versions_are_refused() {
+        #[derive(Serialize)]
+        struct Unversioned<V> {
+            version: Option<V>,
+            #[serde(rename = "type")]
+            kind: &'static str,
+        }
+        let codec = Codec::default();
+
+        let missing = rmp_serde::to_vec_named(&Unversioned::<()> {
+            version: None,
+            kind: "Fire",
+        })
+        .unwrap();
+        let malformed = rmp_serde::to_vec_named(&Unversioned {
+            version: Some("1.0"),
+            kind: "Fire",
+        })
+        .unwrap();
+
+        for frame in [missing, malformed] {
+            assert_eq!(
+                codec.decode::<ServerMessage>(&frame).unwrap_err(),
+                CodecError::BadVersion
+            );
+        }
+    }
+
+    #[test]
+    fn incompatible_clients_understand_rejections() {
+        let rejected = ServerMessage {
+            version: OTHER_VERSION,
+            sender: SenderInfo { id: Uuid::nil() },
+            kind: ServerMessageType::Rejected {
+                reason: "too old".to_string(),
+            },
+        };
+        let codec = Codec::default();
+        let frame = codec.encode(&rejected).unwrap();
+
+        let message: ServerMessage = codec.decode(&frame).unwrap();
+        assert!(
+            matches!(message.kind, ServerMessageType::Rejected { reason } if reason == "too old")
+        );
+    }
+
+    #[test]
+    fn unknown_messages_of_incompatible_versions_report_the_version() {
+        let codec = Codec::default();
+        assert_eq!(
+            codec
+                .decode::<ServerMessage>(&raw_frame(OTHER_VERSION, "Teleport"))
+                .unwrap_err(),
+            CodecError::IncompatibleVersion(OTHER_VERSION)
+        );
+    }
+}
@@ -1,5 +1,11 @@
+pub mod codec;
+pub mod media;
 pub mod message;
 pub mod overlay;
+pub mod transfer;
 
+pub use codec::*;
+pub use media::*;
 pub use message::*;
 pub use overlay::*;
+pub use transfer::*;
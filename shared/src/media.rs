@@ -0,0 +1,111 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use uuid::{Builder, Uuid};
+
+use crate::{Sha256Digest, sha256};
+
+/// Where to find the raw encoded data of an `Overlay`.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Media {
+    /// Data sent along the overlay itself, only fit for small media as it holds the connection while being sent.
+    ///
+    /// Stored as `Vec<u8>` for async-friendly transport, but typically consumed as a `&[u8]` during decoding.
+    Inline(#[serde(with = "serde_bytes")] Vec<u8>),
+
+    /// Data referenced by its content, only sent to those who do not have it already.
+    /// See `ClientMessageType::HaveMedia`.
+    Ref(MediaRef),
+}
+
+/// Content address of some media data.
+///
+/// Two media with the same `MediaRef` are considered to be the same media.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MediaRef {
+    /// SHA-256 digest of the data.
+    #[serde(with = "serde_bytes")]
+    pub sha256: Sha256Digest,
+
+    /// Length of the data in bytes.
+    pub len: u64,
+}
+
+impl MediaRef {
+    /// Compute the reference of some data.
+    pub fn of(data: &[u8]) -> Self {
+        Self {
+            sha256: sha256(data),
+            len: data.len() as u64,
+        }
+    }
+
+    /// Id of every transfer of this media, see `OutgoingTransfer`.
+    ///
+    /// Retrying a transfer announces the same id, so that the receiver resumes it instead of starting over.
+    pub fn transfer_id(&self) -> Uuid {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&self.sha256[..16]);
+        Builder::from_custom_bytes(bytes).into_uuid()
+    }
+}
+
+/// Media data indexed by their content, so that the same media never has to be transferred twice.
+#[derive(Default)]
+// TODO : Media are never evicted, the cache should be bounded in size
+pub struct MediaCache {
+    media: HashMap<MediaRef, Arc<Vec<u8>>>,
+}
+
+impl MediaCache {
+    pub fn get(&self, media: &MediaRef) -> Option<&Arc<Vec<u8>>> {
+        self.media.get(media)
+    }
+
+    pub fn contains(&self, media: &MediaRef) -> bool {
+        self.media.contains_key(media)
+    }
+
+    /// Add data to the cache.
+    /// The caller is responsible for `media` being the actual reference of `data`, see `MediaRef::of`.
+    pub fn insert(&mut self, media: MediaRef, data: Arc<Vec<u8>>) {
+        self.media.insert(media, data);
+    }
+
+    /// Media that are not in the cache, without duplicates.
+    pub fn missing(&self, media: &[MediaRef]) -> Vec<MediaRef> {
+        let mut missing: Vec<MediaRef> = Vec::new();
+        for media in media {
+            if !self.contains(media) && !missing.contains(media) {
+                missing.push(*media);
+            }
+        }
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_media_are_listed_once() {
+        let (cached, other) = (MediaRef::of(b"cached"), MediaRef::of(b"other"));
+        let mut cache = MediaCache::default();
+        cache.insert(cached, Arc::new(b"cached".to_vec()));
+
+        assert_eq!(cache.missing(&[cached, other, other]), vec![other]);
+        assert!(cache.missing(&[cached]).is_empty());
+        assert!(cache.missing(&[]).is_empty());
+    }
+
+    #[test]
+    fn media_are_addressed_by_content() {
+        assert_eq!(MediaRef::of(b"cat"), MediaRef::of(b"cat"));
+        assert_ne!(MediaRef::of(b"cat"), MediaRef::of(b"dog"));
+        assert_eq!(
+            MediaRef::of(b"cat").transfer_id(),
+            MediaRef::of(b"cat").transfer_id()
+        );
+    }
+}
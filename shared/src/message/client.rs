@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{DisplayOptions, Overlay, message::version::Version};
+use crate::{DisplayOptions, MediaRef, Overlay, Sha256Digest, message::version::Version};
 
 /// Top-level message sent by a client to the server.
 ///
@@ -28,19 +28,25 @@ pub struct ClientMessage {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum ClientMessageType {
+    /// First message of every connection, no other message is accepted before it.
+    /// The version being negotiated is the one of the enclosing `ClientMessage`.
+    ///
+    /// Answered by either `ServerMessageType::Welcome` or `ServerMessageType::Rejected`.
+    Hello { kind: ClientKind },
+
     /// Request creation of a new party.
-    /// The sender of this `ClientMessageType` will have the `Role::Creator`.
+    /// The sender becomes its creator, the only member able to invite others and disband it.
     CreateParty,
 
     /// Request disbanding of a party.
-    /// Permission checks (e.g. admin rights) are enforced server-side.
+    /// Restricted to the creator of the party, which is enforced server-side.
     DisbandParty { party_id: Uuid },
 
     /// Request to join a party using an invitation link.
     JoinParty { invitation_link: String },
 
     /// Request generation of a new invitation link for a given party.
-    /// Restricted to `User` with `role` of `Role::Admin` or `Role::Creator`.
+    /// Restricted to the creator of the party.
     CreateInvationLink { party_id: Uuid },
 
     /// Broadcast a set of overlays through the server.
@@ -49,6 +55,41 @@ pub enum ClientMessageType {
         options: DisplayOptions,
     },
 
+    /// List media referenced by upcoming overlays, see `Media::Ref`.
+    /// Answered by `ServerMessageType::WantMedia` with the media that must be uploaded.
+    HaveMedia { media: Vec<MediaRef> },
+
+    /// Answer to `ServerMessageType::HaveMedia`, with the media missing from the local cache.
+    /// Each of them is then sent through a chunked transfer.
+    WantMedia { media: Vec<MediaRef> },
+
+    /// Announce data about to be sent in chunks, see `OutgoingTransfer` for the whole sub-protocol.
+    /// Sending it again for the same media resumes the transfer, even from another connection.
+    BeginTransfer {
+        transfer_id: Uuid,
+        total_size: u64,
+        #[serde(with = "serde_bytes")]
+        sha256: Sha256Digest,
+    },
+
+    /// Part of the data of a transfer, chunks are numbered from 0 and sent in order.
+    TransferChunk {
+        transfer_id: Uuid,
+        index: u32,
+        #[serde(with = "serde_bytes")]
+        bytes: Vec<u8>,
+    },
+
+    /// Ask the sender of a transfer to send the data from `offset`.
+    /// Answers a `BeginTransfer`, `offset` is only non-zero when resuming after a reconnection.
+    ResumeTransfer { transfer_id: Uuid, offset: u64 },
+
+    /// Acknowledge the reception of a chunk of a transfer.
+    ChunkAck { transfer_id: Uuid, index: u32 },
+
+    /// Every chunk of the transfer was received and the data matches its digest.
+    TransferComplete { transfer_id: Uuid },
+
     /// Acknowledge successful download of all overlays.
     /// See `ServerMessageType::OverlaysFullAck`, to see it's use.
     OverlaysAck,
@@ -57,6 +98,10 @@ pub enum ClientMessageType {
     /// See `ServerMessageType::RasterizationFullAck`, to see it's use.
     RasterizationAck,
 
+    /// The overlays could not be rasterized, the splash-screen is left out of the batch.
+    /// Relayed to the sender of the batch as `ServerMessageType::RasterizationFailed`.
+    RasterizationFailed { reason: String },
+
     /// Signal readiness to trigger the final action.
     /// The server decides if and when this becomes authoritative.
     Fire,
@@ -64,3 +109,12 @@ pub enum ClientMessageType {
     /// Error emitted by the client.
     Error { message: String },
 }
+
+/// The different components that can connect to the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientKind {
+    /// Receives overlays and displays them.
+    SplashScreen,
+    /// Sends overlays and manages parties.
+    CommandCenter,
+}
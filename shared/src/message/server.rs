@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{DisplayOptions, Overlay, message::version::Version};
+use crate::{DisplayOptions, MediaRef, Overlay, Sha256Digest, message::version::Version};
 
 #[derive(Serialize, Deserialize, Debug)]
 /// Top-level message emitted by the server
@@ -35,13 +35,34 @@ pub struct SenderInfo {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum ServerMessageType {
+    /// Response to `ClientMessageType::Hello`
+    /// The client and server versions are compatible, the client can now send any other message.
+    /// `client_id` is the identifier the server will use as `SenderInfo.id` for this client.
+    Welcome { client_id: Uuid },
+
+    /// Response to `ClientMessageType::Hello`
+    /// The client can't talk with this server, e.g. because of incompatible versions.
+    /// The connection is closed right after this message.
+    Rejected { reason: String },
+
     /// Response to `ClientMessageType::CreateParty`
-    /// Confirms party creation and assigns creator(same as admin) privileges to the requesting client.
-    PartyCreated,
+    /// Confirms party creation and assigns creator privileges to the requesting client.
+    PartyCreated { party_id: Uuid },
+
+    /// Response to `ClientMessageType::CreateInvationLink`
+    /// Contains a link that can be given to other users so that they can join the party.
+    InvitationLinkCreated {
+        party_id: Uuid,
+        invitation_link: String,
+    },
 
     /// Response to `ClientMessageType::JoinParty`
     /// This tells the client he successfully joined the given `Party`
-    JoinAccepted,
+    JoinAccepted { party_id: Uuid },
+
+    /// Relay of the `ClientMessageType::DisbandParty`, also sent when the creator of the party disconnects.
+    /// Sent to every member of the party, they are no longer part of it afterwards.
+    PartyDisbanded { party_id: Uuid },
 
     /// Relay of the `ClientMessageType::Overlays`
     /// Contains the full set of overlays to be displayed along with metadata in `options` to adjust the displaying.
@@ -50,6 +71,41 @@ pub enum ServerMessageType {
         options: DisplayOptions,
     },
 
+    /// List media referenced by the upcoming `Overlays`, see `Media::Ref`.
+    /// Answered by `ClientMessageType::WantMedia` with the media missing from the splash-screen cache.
+    HaveMedia { media: Vec<MediaRef> },
+
+    /// Answer to `ClientMessageType::HaveMedia`, with the media the server does not have yet.
+    /// Each of them must be uploaded through a chunked transfer.
+    WantMedia { media: Vec<MediaRef> },
+
+    /// Announce data about to be sent in chunks, see `OutgoingTransfer` for the whole sub-protocol.
+    /// Used to send the media wanted by splash-screens, see `ClientMessageType::WantMedia`.
+    BeginTransfer {
+        transfer_id: Uuid,
+        total_size: u64,
+        #[serde(with = "serde_bytes")]
+        sha256: Sha256Digest,
+    },
+
+    /// Part of the data of a transfer, chunks are numbered from 0 and sent in order.
+    TransferChunk {
+        transfer_id: Uuid,
+        index: u32,
+        #[serde(with = "serde_bytes")]
+        bytes: Vec<u8>,
+    },
+
+    /// Ask the sender of a transfer to send the data from `offset`.
+    /// Answers a `BeginTransfer`, `offset` is only non-zero when resuming after a reconnection.
+    ResumeTransfer { transfer_id: Uuid, offset: u64 },
+
+    /// Acknowledge the reception of a chunk of a transfer.
+    ChunkAck { transfer_id: Uuid, index: u32 },
+
+    /// Every chunk of the transfer was received and the data matches its digest.
+    TransferComplete { transfer_id: Uuid },
+
     /// Aggregate of `ClientMessageType::OverlaysAck`
     /// Sent when all online members of a party have downloaded the `Overlays`
     OverlaysFullAck,
@@ -58,6 +114,10 @@ pub enum ServerMessageType {
     /// Sent when all online members of a party have rasterized all the `Overlays`
     RasterizationFullAck,
 
+    /// Relay of the `ClientMessageType::RasterizationFailed`, sent to the sender of the batch.
+    /// The splash-screen that failed (`SenderInfo.id`) is no longer waited on, nor will it receive `Fire`.
+    RasterizationFailed { reason: String },
+
     /// Relay of the `ClientMessageType::Fire`
     /// Can only be sent once `OverlaysFullAck` and `RasterizationFullAck` have been emitted
     Fire,
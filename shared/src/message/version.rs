@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 
@@ -7,10 +7,15 @@ use serde::{Deserialize, Serialize};
 ///
 /// Follows `MAJOR.MINOR.PATCH` semantics.
 /// See https://semver.org/
-#[derive(Serialize, Deserialize, Debug)]
+///
+/// Versions are ordered by `major`, then `minor`, then `patch`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Version {
     /// Gets increment when there are breaking changes in the protocol.
     /// Implies no backward compatibility with other `major` versions.
+    ///
+    /// Major version zero is for initial development, where `minor` is used for breaking changes instead.
+    /// See https://semver.org/#spec-item-4
     major: u8,
     /// Gets incremented when there are backward compatible changes in the protocol.
     /// Imoplies backward compatibility with other `minor` versions.
@@ -20,13 +25,56 @@ pub struct Version {
 }
 
 impl Version {
-    fn new(major: u8, minor: u8, patch: u8) -> Self {
+    /// Version of the protocol implemented by this crate, taken from the crate version.
+    pub const CURRENT: Version = Version::new(
+        parse_component(env!("CARGO_PKG_VERSION_MAJOR")),
+        parse_component(env!("CARGO_PKG_VERSION_MINOR")),
+        parse_component(env!("CARGO_PKG_VERSION_PATCH")),
+    );
+
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
         Self {
             major,
             minor,
             patch,
         }
     }
+
+    /// Whether messages of both versions can be understood by each other.
+    ///
+    /// Versions are compatible when they share the same `major` version,
+    /// or the same `minor` version while `major` is still zero.
+    pub fn is_compatible_with(&self, other: &Version) -> bool {
+        if self.major == 0 {
+            self.major == other.major && self.minor == other.minor
+        } else {
+            self.major == other.major
+        }
+    }
+}
+
+/// `u8::from_str` is not usable in const contexts, this is only meant for `Version::CURRENT`.
+const fn parse_component(s: &str) -> u8 {
+    let bytes = s.as_bytes();
+    let mut value: u8 = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        assert!(
+            bytes[i].is_ascii_digit(),
+            "version component is not a number"
+        );
+        value = value * 10 + (bytes[i] - b'0');
+        i += 1;
+    }
+
+    value
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
 }
 
 /// Errors that can occur while parsing a semantic version string.
@@ -81,3 +129,53 @@ impl TryFrom<&str> for Version {
         value.parse()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_major_versions_are_compatible() {
+        let version = Version::new(1, 2, 3);
+        assert!(version.is_compatible_with(&Version::new(1, 0, 0)));
+        assert!(version.is_compatible_with(&Version::new(1, 9, 9)));
+        assert!(!version.is_compatible_with(&Version::new(2, 2, 3)));
+        assert!(!version.is_compatible_with(&Version::new(0, 2, 3)));
+    }
+
+    #[test]
+    fn minor_versions_break_compatibility_before_1_0_0() {
+        let version = Version::new(0, 2, 0);
+        assert!(version.is_compatible_with(&Version::new(0, 2, 9)));
+        assert!(!version.is_compatible_with(&Version::new(0, 3, 0)));
+        assert!(!version.is_compatible_with(&Version::new(0, 1, 0)));
+        assert!(!version.is_compatible_with(&Version::new(1, 2, 0)));
+    }
+
+    #[test]
+    fn versions_are_parsed_from_strings() {
+        assert_eq!("1.20.3".parse(), Ok(Version::new(1, 20, 3)));
+        assert_eq!(Version::new(1, 20, 3).to_string(), "1.20.3");
+        assert_eq!(
+            "1.2".parse::<Version>(),
+            Err(VersionParseError::InvalidFormat)
+        );
+        assert_eq!(
+            "1.2.3.4".parse::<Version>(),
+            Err(VersionParseError::InvalidFormat)
+        );
+        assert_eq!(
+            "1.x.3".parse::<Version>(),
+            Err(VersionParseError::InvalidNumber)
+        );
+        assert_eq!(
+            "1.256.3".parse::<Version>(),
+            Err(VersionParseError::InvalidNumber)
+        );
+    }
+
+    #[test]
+    fn current_version_follows_the_crate() {
+        assert_eq!(Version::CURRENT.to_string(), env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -1,20 +1,38 @@
 use serde::{Deserialize, Serialize};
 
+use crate::Media;
+
 /// Media that can be decoded/rasterized and composited onto a `Frame`.
 #[derive(Serialize, Deserialize, Debug)]
-// Self-contained because they go through a different canal than traditionnal messages because of their size.
-// TODO : Add a timeout_ms, an overlay should be able to last not as long as the overall media.
+// Media can go through a different canal than traditionnal messages because of their size, see `Media`.
 pub enum Overlay {
     Text {
         /// UTF-8 text content to render.
         text: String,
 
-        /// Font size in logical pixels.
+        /// Runs of text with their own style, replacing `text` when not empty.
+        /// Their unset attributes are the ones of the overlay.
+        #[serde(default)]
+        spans: Vec<TextSpan>,
+
+        /// Font size in logical pixels, from 1 to 1024.
         size: u32,
 
         /// RGBA color (0–255 per channel).
         color: [u8; 4],
 
+        /// Wrapping, alignment and spacing of the lines.
+        #[serde(default)]
+        layout: TextLayout,
+
+        /// Font the text is rendered with, `None` for the default font bundled with the splash-screen.
+        #[serde(default)]
+        font: Option<Font>,
+
+        /// Outline, shadow and background of the text, to keep it readable on any background.
+        #[serde(default)]
+        effects: TextEffects,
+
         /// Horizontal offset from the left edge. We are using the top-left corner as the origin as seen in CSSOM.
         /// See https://developer.mozilla.org/en-US/docs/Web/API/CSSOM_view_API/Coordinate_systems
         ///
@@ -29,14 +47,28 @@ pub enum Overlay {
 
         /// Z-order for composition (0 = back, high = front).
         z_index: u32,
+
+        /// Delay in milliseconds between the `Fire` moment and the overlay appearing.
+        #[serde(default)]
+        start_ms: u32,
+
+        /// Duration in milliseconds the overlay remains visible once it appeared.
+        /// `None` keeps it visible until the end of the batch, see `DisplayOptions.timeout_ms`.
+        #[serde(default)]
+        duration_ms: Option<u32>,
+
+        /// Size, rotation and opacity of the overlay, applied once when it is rasterized.
+        #[serde(default)]
+        transform: Transform,
+
+        /// Resolution-independent alternative to `offset_left` and `offset_top`, which are ignored when it is set.
+        #[serde(default)]
+        position: Option<Position>,
     },
     Image {
         /// Raw encoded image data (PNG / JPEG / WebP / etc).
         /// This data is decoded via the `image` crate, thus any variant shown [here](https://docs.rs/image/latest/image/enum.ImageFormat.html) that is **not** animated can be decoded.
-        ///
-        /// Stored as `Vec<u8>` for async-friendly transport, but typically consumed as a `&[u8]` during decoding.
-        #[serde(with = "serde_bytes")]
-        bytes: Vec<u8>,
+        media: Media,
 
         /// Horizontal offset from the left edge. We are using the top-left corner as the origin as seen in CSSOM.
         /// See https://developer.mozilla.org/en-US/docs/Web/API/CSSOM_view_API/Coordinate_systems
@@ -52,15 +84,29 @@ pub enum Overlay {
 
         /// Z-order for composition (0 = back, high = front).
         z_index: u32,
+
+        /// Delay in milliseconds between the `Fire` moment and the overlay appearing.
+        #[serde(default)]
+        start_ms: u32,
+
+        /// Duration in milliseconds the overlay remains visible once it appeared.
+        /// `None` keeps it visible until the end of the batch, see `DisplayOptions.timeout_ms`.
+        #[serde(default)]
+        duration_ms: Option<u32>,
+
+        /// Size, rotation and opacity of the overlay, applied once when it is rasterized.
+        #[serde(default)]
+        transform: Transform,
+
+        /// Resolution-independent alternative to `offset_left` and `offset_top`, which are ignored when it is set.
+        #[serde(default)]
+        position: Option<Position>,
     },
 
     AnimatedImage {
-        /// Raw encoded image data (GIF / APNG).
+        /// Raw encoded image data (GIF / APNG / WebP).
         /// This data is decoded via the `image` crate, thus any variant shown [here](https://docs.rs/image/latest/image/enum.ImageFormat.html) that is animated can be decoded.
-        ///
-        /// Stored as `Vec<u8>` for async-friendly transport, but typically consumed as a `&[u8]` during decoding.
-        #[serde(with = "serde_bytes")]
-        bytes: Vec<u8>,
+        media: Media,
 
         /// Horizontal offset from the left edge. We are using the top-left corner as the origin as seen in CSSOM.
         /// See https://developer.mozilla.org/en-US/docs/Web/API/CSSOM_view_API/Coordinate_systems
@@ -76,9 +122,288 @@ pub enum Overlay {
 
         /// Z-order for composition (0 = back, high = front).
         z_index: u32,
+
+        /// Delay in milliseconds between the `Fire` moment and the overlay appearing.
+        #[serde(default)]
+        start_ms: u32,
+
+        /// Duration in milliseconds the overlay remains visible once it appeared.
+        /// `None` keeps it visible until the end of the batch, see `DisplayOptions.timeout_ms`.
+        #[serde(default)]
+        duration_ms: Option<u32>,
+
+        /// Size, rotation and opacity of the overlay, applied once when it is rasterized.
+        #[serde(default)]
+        transform: Transform,
+
+        /// Resolution-independent alternative to `offset_left` and `offset_top`, which are ignored when it is set.
+        #[serde(default)]
+        position: Option<Position>,
     },
 }
 
+impl Overlay {
+    /// Raw encoded data the overlay needs to be rasterized, like the image itself or the font of `Overlay::Text`.
+    pub fn media(&self) -> Option<&Media> {
+        match self {
+            Overlay::Text { font, .. } => font.as_ref().and_then(|font| font.data.as_ref()),
+            Overlay::Image { media, .. } | Overlay::AnimatedImage { media, .. } => Some(media),
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        match self {
+            Overlay::Text { transform, .. }
+            | Overlay::Image { transform, .. }
+            | Overlay::AnimatedImage { transform, .. } => transform,
+        }
+    }
+
+    /// Where the overlay is placed, `Position::Absolute` when only its offsets are given.
+    pub fn position(&self) -> Position {
+        match *self {
+            Overlay::Text {
+                offset_left,
+                offset_top,
+                position,
+                ..
+            }
+            | Overlay::Image {
+                offset_left,
+                offset_top,
+                position,
+                ..
+            }
+            | Overlay::AnimatedImage {
+                offset_left,
+                offset_top,
+                position,
+                ..
+            } => position.unwrap_or(Position::Absolute {
+                left: offset_left,
+                top: offset_top,
+            }),
+        }
+    }
+}
+
+/// Font of an `Overlay::Text`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Font {
+    /// Family name of the font, e.g. "Comic Sans MS".
+    pub family: String,
+
+    /// Raw font data (TTF / OTF / TTC) containing `family`, loaded once by each splash-screen and kept for the following batches, within a limit.
+    /// Without it the family must be installed on the splash-screen, which falls back to its default font otherwise.
+    #[serde(default)]
+    pub data: Option<Media>,
+}
+
+/// A run of an `Overlay::Text`, styled differently from the rest of the text.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TextSpan {
+    /// UTF-8 text content of the run, which may span several lines.
+    pub text: String,
+
+    /// RGBA color (0–255 per channel).
+    #[serde(default)]
+    pub color: Option<[u8; 4]>,
+
+    /// Font size in logical pixels.
+    #[serde(default)]
+    pub size: Option<u32>,
+
+    /// Thickness of the font from 100 (thin) to 900 (black), 400 being regular and 700 bold.
+    #[serde(default)]
+    pub weight: Option<u16>,
+
+    #[serde(default)]
+    pub italic: bool,
+
+    /// Family of the font, which must be installed on the splash-screen or sent along the batch (see `Font.data`).
+    #[serde(default)]
+    pub family: Option<String>,
+}
+
+/// Decorations drawn behind the glyphs of an `Overlay::Text`, none by default.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct TextEffects {
+    pub stroke: Option<TextStroke>,
+    pub shadow: Option<TextShadow>,
+    pub background: Option<TextBackground>,
+}
+
+/// Outline around every glyph.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TextStroke {
+    /// Thickness of the outline in pixels.
+    pub width: f32,
+
+    /// RGBA color (0–255 per channel).
+    pub color: [u8; 4],
+}
+
+/// Shadow of the glyphs and their outline.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TextShadow {
+    /// Horizontal offset from the glyphs in pixels, to the right when positive.
+    pub offset_x: i32,
+
+    /// Vertical offset from the glyphs in pixels, downward when positive.
+    pub offset_y: i32,
+
+    /// How much the shadow is blurred in pixels, as the CSS `text-shadow` blur radius. 0 for a sharp shadow.
+    #[serde(default)]
+    pub blur_radius: f32,
+
+    /// RGBA color (0–255 per channel).
+    pub color: [u8; 4],
+}
+
+/// Rectangle behind the lines of text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TextBackground {
+    /// Space between the lines and the edges of the rectangle in pixels.
+    #[serde(default)]
+    pub padding: u32,
+
+    /// Radius of the rounded corners in pixels, 0 for square corners.
+    #[serde(default)]
+    pub corner_radius: f32,
+
+    /// RGBA color (0–255 per channel).
+    pub color: [u8; 4],
+}
+
+/// How the lines of an `Overlay::Text` are laid out.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct TextLayout {
+    /// Width in pixels after which lines wrap, `None` to only break lines on `\n`.
+    pub max_width: Option<u32>,
+
+    /// Horizontal alignment of the lines relative to each other.
+    pub align: TextAlign,
+
+    /// Distance between two lines, as a multiple of the font size.
+    pub line_height: f32,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            align: TextAlign::Left,
+            // TODO : change this for a better heuristic. See https://grtcalculator.com/math/
+            line_height: 1.2,
+        }
+    }
+}
+
+/// Horizontal alignment of text, see `TextLayout`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Lines are stretched to the width of the text, but the last line of each paragraph.
+    Justify,
+}
+
+/// Geometric and color changes applied to an `Overlay`, on top of its native size.
+///
+/// The default transform leaves the overlay untouched.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Transform {
+    /// Size of the overlay once resampled.
+    pub size: Size,
+
+    /// Clockwise rotation in degrees, around the `anchor`.
+    pub rotation_deg: f32,
+
+    /// Opacity multiplied with the alpha of every pixel, from 0.0 (invisible) to 1.0 (unchanged).
+    pub opacity: f32,
+
+    /// Point of the overlay placed at its offsets, and around which it rotates.
+    pub anchor: Anchor,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            size: Size::Native,
+            rotation_deg: 0.0,
+            opacity: 1.0,
+            anchor: Anchor::TOP_LEFT,
+        }
+    }
+}
+
+/// Size of an `Overlay`, see `Transform`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum Size {
+    /// Native pixel size of the media.
+    #[default]
+    Native,
+
+    /// Native size multiplied by a factor on both axes, e.g. 0.5 to halve it.
+    Scale(f32),
+
+    /// Size in pixels. When only one dimension is given, the other one keeps the aspect ratio.
+    Pixels {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+
+    /// Height as a fraction of the height of the canvas, e.g. 0.1 for a tenth of the screen.
+    /// The width keeps the aspect ratio.
+    CanvasHeight(f32),
+}
+
+/// Where an `Overlay` is placed on the canvas of a splash-screen, see `Transform.anchor` for the point of the
+/// overlay that ends up there.
+///
+/// Splash-screens do not share the same resolution, relative positions look the same on all of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    /// Offsets in pixels from the top-left corner of the canvas, as `offset_left` and `offset_top`.
+    Absolute { left: i32, top: i32 },
+
+    /// Offsets as fractions of the size of the canvas, from one of its points.
+    ///
+    /// e.g. `origin: Anchor::BOTTOM_RIGHT, left: -0.05, top: -0.05` is near the bottom-right corner of every screen.
+    Relative {
+        /// Point of the canvas the offsets start from.
+        origin: Anchor,
+        /// Horizontal offset as a fraction of the canvas width, towards the right.
+        left: f32,
+        /// Vertical offset as a fraction of the canvas height, towards the bottom.
+        top: f32,
+    },
+}
+
+/// A point relative to the size of a rectangle, from (0.0, 0.0) at its top-left corner to (1.0, 1.0) at its bottom-right.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Anchor = Anchor { x: 0.0, y: 0.0 };
+    pub const TOP: Anchor = Anchor { x: 0.5, y: 0.0 };
+    pub const TOP_RIGHT: Anchor = Anchor { x: 1.0, y: 0.0 };
+    pub const LEFT: Anchor = Anchor { x: 0.0, y: 0.5 };
+    pub const CENTER: Anchor = Anchor { x: 0.5, y: 0.5 };
+    pub const RIGHT: Anchor = Anchor { x: 1.0, y: 0.5 };
+    pub const BOTTOM_LEFT: Anchor = Anchor { x: 0.0, y: 1.0 };
+    pub const BOTTOM: Anchor = Anchor { x: 0.5, y: 1.0 };
+    pub const BOTTOM_RIGHT: Anchor = Anchor { x: 1.0, y: 1.0 };
+}
+
 /// Global display parameters applied to a batch of overlays.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DisplayOptions {
@@ -0,0 +1,353 @@
+use std::{fmt, sync::Arc};
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Size of every chunk of a transfer but the last one, in bytes.
+///
+/// Small enough so that a chunk never holds the connection for long,
+/// letting other messages through in between.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Maximum number of chunks sent without having been acknowledged yet.
+pub const WINDOW_SIZE: u32 = 8;
+
+/// Maximum number of uploads a client can have going on at once, more are
+/// refused until one of them completes.
+pub const MAX_CONCURRENT_UPLOADS: usize = 4;
+
+/// SHA-256 digest of the data of a transfer.
+pub type Sha256Digest = [u8; 32];
+
+/// Compute the SHA-256 digest of some data.
+pub fn sha256(data: &[u8]) -> Sha256Digest {
+    Sha256::digest(data).into()
+}
+
+/// Sending side of a chunked transfer.
+///
+/// The sub-protocol goes as follow, in both directions (client to server, server to client) :
+/// 1. The sender announces the data with a `BeginTransfer`
+/// 2. The receiver answers with `ResumeTransfer`, giving the offset it wants the data from (0 for a new transfer)
+/// 3. The sender sends numbered `TransferChunk`, which the receiver acknowledges one by one with `ChunkAck`
+/// 4. The receiver checks the digest of the data and sends `TransferComplete`
+///
+/// After a reconnection, the sender announces the same data again, with the same id (see `MediaRef::transfer_id`).
+/// The receiver keeps what it already received, and answers with the offset it reached so that only missing chunks are sent.
+pub struct OutgoingTransfer {
+    transfer_id: Uuid,
+    data: Arc<Vec<u8>>,
+    sha256: Sha256Digest,
+
+    /// Index of the next chunk to send.
+    /// `None` until the receiver tells us where to start from.
+    next_index: Option<u32>,
+
+    /// Number of chunks acknowledged by the receiver, chunks are always acknowledged in order.
+    acked: u32,
+}
+
+impl OutgoingTransfer {
+    pub fn new(transfer_id: Uuid, data: Arc<Vec<u8>>) -> Self {
+        let sha256 = sha256(&data);
+        Self::with_digest(transfer_id, data, sha256)
+    }
+
+    /// Create a transfer from data whose digest is already known, saving from hashing it again.
+    pub fn with_digest(transfer_id: Uuid, data: Arc<Vec<u8>>, sha256: Sha256Digest) -> Self {
+        Self {
+            transfer_id,
+            data,
+            sha256,
+            next_index: None,
+            acked: 0,
+        }
+    }
+
+    pub fn transfer_id(&self) -> Uuid {
+        self.transfer_id
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn sha256(&self) -> Sha256Digest {
+        self.sha256
+    }
+
+    fn chunk_count(&self) -> u32 {
+        self.data.len().div_ceil(CHUNK_SIZE) as u32
+    }
+
+    /// (Re)start sending chunks from the offset asked by the receiver.
+    ///
+    /// The offset is either at a chunk boundary or at the end, when the receiver already has everything.
+    pub fn resume_from(&mut self, offset: u64) -> Result<(), TransferError> {
+        let index = if offset == self.total_size() {
+            self.chunk_count()
+        } else if offset.is_multiple_of(CHUNK_SIZE as u64) && offset < self.total_size() {
+            (offset / CHUNK_SIZE as u64) as u32
+        } else {
+            return Err(TransferError::InvalidOffset(offset));
+        };
+        self.next_index = Some(index);
+        self.acked = index;
+        Ok(())
+    }
+
+    /// Take the next chunk to send, along with its index.
+    ///
+    /// Returns `None` when waiting on the receiver, either for the offset to start from
+    /// or for acknowledgements because too many chunks are in flight.
+    pub fn next_chunk(&mut self) -> Option<(u32, &[u8])> {
+        let index = self.next_index?;
+        if index >= self.chunk_count() || index.saturating_sub(self.acked) >= WINDOW_SIZE {
+            return None;
+        }
+        self.next_index = Some(index + 1);
+
+        let start = index as usize * CHUNK_SIZE;
+        let end = (start + CHUNK_SIZE).min(self.data.len());
+        Some((index, &self.data[start..end]))
+    }
+
+    /// Mark every chunk up to `index` (included) as received.
+    pub fn ack(&mut self, index: u32) {
+        let sent = self.next_index.unwrap_or(0);
+        self.acked = self.acked.max(index.saturating_add(1)).min(sent);
+    }
+
+    /// Whether every chunk was acknowledged by the receiver.
+    pub fn is_acked(&self) -> bool {
+        self.next_index.is_some() && self.acked == self.chunk_count()
+    }
+}
+
+/// Receiving side of a chunked transfer, see `OutgoingTransfer` for the whole sub-protocol.
+pub struct IncomingTransfer {
+    total_size: u64,
+    sha256: Sha256Digest,
+    /// Data received so far, chunks are always received in order.
+    data: Vec<u8>,
+}
+
+impl IncomingTransfer {
+    pub fn new(total_size: u64, sha256: Sha256Digest) -> Self {
+        Self {
+            total_size,
+            sha256,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    pub fn sha256(&self) -> Sha256Digest {
+        self.sha256
+    }
+
+    /// Amount of bytes received so far, this is where the sender should resume from.
+    pub fn offset(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Append a chunk to the data received so far.
+    pub fn push(&mut self, index: u32, bytes: &[u8]) -> Result<(), TransferError> {
+        let expected = (self.data.len() / CHUNK_SIZE) as u32;
+        if index != expected || self.is_complete() {
+            return Err(TransferError::UnexpectedChunk {
+                expected,
+                received: index,
+            });
+        }
+
+        // Only the last chunk can be smaller than the others
+        let remaining = self.total_size - self.offset();
+        if bytes.len() as u64 != remaining.min(CHUNK_SIZE as u64) {
+            return Err(TransferError::InvalidChunkSize(bytes.len()));
+        }
+
+        self.data.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.offset() == self.total_size
+    }
+
+    /// Check the received data against the announced digest, and return it.
+    pub fn finish(self) -> Result<Vec<u8>, TransferError> {
+        if !self.is_complete() {
+            return Err(TransferError::Incomplete);
+        }
+        if sha256(&self.data) != self.sha256 {
+            return Err(TransferError::DigestMismatch);
+        }
+        Ok(self.data)
+    }
+}
+
+/// Errors that can occur during a chunked transfer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransferError {
+    /// Chunks must be received in order, without duplicates.
+    UnexpectedChunk { expected: u32, received: u32 },
+    /// Every chunk must be `CHUNK_SIZE` long, except the last one.
+    InvalidChunkSize(usize),
+    /// Transfers can only resume from the start of a chunk, inside the data.
+    InvalidOffset(u64),
+    /// Some chunks were not received yet.
+    Incomplete,
+    /// The received data does not match the announced digest.
+    DigestMismatch,
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferError::UnexpectedChunk { expected, received } => {
+                write!(f, "expected chunk {expected}, received chunk {received}")
+            }
+            TransferError::InvalidChunkSize(size) => {
+                write!(f, "invalid chunk size of {size} bytes")
+            }
+            TransferError::InvalidOffset(offset) => write!(f, "cannot resume from offset {offset}"),
+            TransferError::Incomplete => write!(f, "transfer is incomplete"),
+            TransferError::DigestMismatch => write!(f, "data does not match its SHA-256 digest"),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data spanning a few more chunks than the window, the last one being partial.
+    fn data() -> Arc<Vec<u8>> {
+        let len = (WINDOW_SIZE as usize + 2) * CHUNK_SIZE + 1;
+        Arc::new((0..len).map(|i| i as u8).collect())
+    }
+
+    /// Send every chunk allowed by the window to the receiver, acknowledging them.
+    fn exchange(outgoing: &mut OutgoingTransfer, incoming: &mut IncomingTransfer) -> usize {
+        let mut chunks = Vec::new();
+        while let Some((index, bytes)) = outgoing.next_chunk() {
+            chunks.push((index, bytes.to_vec()));
+        }
+        for (index, bytes) in &chunks {
+            incoming.push(*index, bytes).unwrap();
+            outgoing.ack(*index);
+        }
+        chunks.len()
+    }
+
+    #[test]
+    fn chunks_are_sent_within_the_window() {
+        let mut outgoing = OutgoingTransfer::new(Uuid::nil(), data());
+        // Nothing is sent before the receiver tells where to start from
+        assert!(outgoing.next_chunk().is_none());
+
+        outgoing.resume_from(0).unwrap();
+        for expected in 0..WINDOW_SIZE {
+            assert_eq!(
+                outgoing.next_chunk().map(|(index, _)| index),
+                Some(expected)
+            );
+        }
+        assert!(outgoing.next_chunk().is_none());
+
+        // Every acknowledged chunk lets another one through
+        outgoing.ack(0);
+        assert_eq!(
+            outgoing.next_chunk().map(|(index, _)| index),
+            Some(WINDOW_SIZE)
+        );
+        assert!(outgoing.next_chunk().is_none());
+
+        // Chunks that were not sent yet cannot be acknowledged
+        outgoing.ack(100);
+        assert!(!outgoing.is_acked());
+    }
+
+    #[test]
+    fn transfers_deliver_the_whole_data() {
+        let data = data();
+        let mut outgoing = OutgoingTransfer::new(Uuid::nil(), data.clone());
+        let mut incoming = IncomingTransfer::new(outgoing.total_size(), outgoing.sha256());
+
+        outgoing.resume_from(incoming.offset()).unwrap();
+        while exchange(&mut outgoing, &mut incoming) > 0 {}
+
+        assert!(outgoing.is_acked());
+        assert!(incoming.is_complete());
+        assert_eq!(incoming.finish().unwrap(), *data);
+    }
+
+    #[test]
+    fn transfers_resume_from_the_received_data() {
+        let data = data();
+        let mut outgoing = OutgoingTransfer::new(Uuid::nil(), data.clone());
+        let mut incoming = IncomingTransfer::new(outgoing.total_size(), outgoing.sha256());
+        outgoing.resume_from(0).unwrap();
+        exchange(&mut outgoing, &mut incoming);
+
+        // The sender reconnects and announces the data again
+        let mut outgoing = OutgoingTransfer::new(Uuid::nil(), data.clone());
+        outgoing.resume_from(incoming.offset()).unwrap();
+        assert_eq!(
+            outgoing.next_chunk().map(|(index, _)| index),
+            Some(WINDOW_SIZE)
+        );
+
+        assert_eq!(
+            outgoing.resume_from(1),
+            Err(TransferError::InvalidOffset(1))
+        );
+        assert_eq!(
+            outgoing.resume_from(data.len() as u64 + 1),
+            Err(TransferError::InvalidOffset(data.len() as u64 + 1))
+        );
+
+        // The receiver already has everything, even though the last chunk is not full
+        outgoing.resume_from(data.len() as u64).unwrap();
+        assert_eq!(outgoing.next_chunk(), None);
+        assert!(outgoing.is_acked());
+    }
+
+    #[test]
+    fn chunks_must_be_in_order_and_full() {
+        let data = data();
+        let mut incoming = IncomingTransfer::new(data.len() as u64, sha256(&data));
+
+        assert_eq!(
+            incoming.push(1, &data[..CHUNK_SIZE]),
+            Err(TransferError::UnexpectedChunk {
+                expected: 0,
+                received: 1
+            })
+        );
+        assert_eq!(
+            incoming.push(0, &data[..10]),
+            Err(TransferError::InvalidChunkSize(10))
+        );
+        incoming.push(0, &data[..CHUNK_SIZE]).unwrap();
+        assert_eq!(incoming.finish(), Err(TransferError::Incomplete));
+    }
+
+    #[test]
+    fn data_must_match_its_digest() {
+        let data = data();
+        let mut outgoing = OutgoingTransfer::with_digest(Uuid::nil(), data.clone(), [0; 32]);
+        let mut incoming = IncomingTransfer::new(outgoing.total_size(), outgoing.sha256());
+
+        outgoing.resume_from(0).unwrap();
+        while exchange(&mut outgoing, &mut incoming) > 0 {}
+
+        assert_eq!(incoming.finish(), Err(TransferError::DigestMismatch));
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use ff::{
+    ClientKind, ClientMessage, ClientMessageType, Codec, IncomingTransfer, MediaCache, MediaRef,
+    ServerMessage, ServerMessageType, Version,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream,
+    tungstenite::{Message, protocol::WebSocketConfig},
+};
+use uuid::Uuid;
+
+use crate::RenderEvent;
+
+/// Connection of the splash-screen to the relay server.
+///
+/// It takes care of everything the render loop does not need to know about (handshake, party, media transfers),
+/// and forwards the rest of the `ServerMessage` to it.
+///
+/// Everything is logged to stderr, as stdout may be used to stream frames (see `FrameOutput::RawStdout`).
+pub struct Client {
+    codec: Codec,
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+
+    /// Media received from the server, shared with the render loop which rasterizes the overlays.
+    media_cache: Arc<RwLock<MediaCache>>,
+    /// Media being downloaded, see `ff::OutgoingTransfer` for the sub-protocol.
+    downloads: HashMap<Uuid, IncomingTransfer>,
+    /// Media announced by `ServerMessageType::HaveMedia` that were not received yet.
+    missing_media: HashSet<MediaRef>,
+    /// Overlays waiting on `missing_media` before being acknowledged.
+    pending_overlays: Option<ServerMessage>,
+
+    /// `ServerMessage` the render loop cares about.
+    messages: mpsc::Sender<ServerMessage>,
+}
+
+impl Client {
+    /// Connect to the server and introduce ourselves as a splash-screen.
+    pub async fn connect(
+        address: &str,
+        media_cache: Arc<RwLock<MediaCache>>,
+        messages: mpsc::Sender<ServerMessage>,
+    ) -> anyhow::Result<Self> {
+        let codec = Codec::default();
+        let config = WebSocketConfig::default().max_message_size(Some(codec.max_frame_size()));
+        let (socket, _) =
+            tokio_tungstenite::connect_async_with_config(address, Some(config), false).await?;
+
+        let mut client = Self {
+            codec,
+            socket,
+            media_cache,
+            downloads: HashMap::new(),
+            missing_media: HashSet::new(),
+            pending_overlays: None,
+            messages,
+        };
+
+        client
+            .send(ClientMessageType::Hello {
+                kind: ClientKind::SplashScreen,
+            })
+            .await?;
+        match client.receive().await?.kind {
+            ServerMessageType::Welcome { client_id } => eprintln!("Connected as {client_id}"),
+            ServerMessageType::Rejected { reason } => {
+                anyhow::bail!("Rejected by the server: {reason}")
+            }
+            kind => anyhow::bail!("Expected a Welcome from the server, received {kind:?}"),
+        }
+
+        Ok(client)
+    }
+
+    /// Join the party an invitation link leads to.
+    pub async fn join(&mut self, invitation_link: &str) -> anyhow::Result<()> {
+        self.send(ClientMessageType::JoinParty {
+            invitation_link: invitation_link.to_string(),
+        })
+        .await?;
+
+        match self.receive().await?.kind {
+            ServerMessageType::JoinAccepted { party_id } => eprintln!("Joined party {party_id}"),
+            ServerMessageType::Error { message } => anyhow::bail!("Could not join: {message}"),
+            kind => anyhow::bail!("Expected the party to be joined, received {kind:?}"),
+        }
+
+        Ok(())
+    }
+
+    /// Handle messages from the server, along with the events of the render loop, until the server goes away.
+    pub async fn run(
+        mut self,
+        mut events: mpsc::UnboundedReceiver<RenderEvent>,
+    ) -> anyhow::Result<()> {
+        loop {
+            tokio::select! {
+                message = self.socket.next() => {
+                    let Some(message) = message else {
+                        return Ok(());
+                    };
+
+                    match message? {
+                        Message::Binary(bytes) => match self.codec.decode::<ServerMessage>(&bytes) {
+                            Ok(message) => self.handle(message).await?,
+                            Err(err) => {
+                                eprintln!("Invalid message from the server: {err}");
+                                self.send(ClientMessageType::Error {
+                                    message: format!("Invalid message: {err}"),
+                                })
+                                .await?;
+                            }
+                        },
+                        Message::Close(_) => return Ok(()),
+                        // Pings are answered by tungstenite itself
+                        Message::Text(_) | Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+                    }
+                }
+
+                Some(event) = events.recv() => match event {
+                    RenderEvent::Rasterized => self.send(ClientMessageType::RasterizationAck).await?,
+                    RenderEvent::RasterizationFailed(reason) => {
+                        self.send(ClientMessageType::RasterizationFailed { reason }).await?;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn handle(&mut self, message: ServerMessage) -> anyhow::Result<()> {
+        match message.kind {
+            ServerMessageType::HaveMedia { media } => {
+                let missing = self.cache().missing(&media);
+                if !missing.is_empty() {
+                    self.missing_media.extend(missing.iter().copied());
+                    self.send(ClientMessageType::WantMedia { media: missing })
+                        .await?;
+                }
+            }
+
+            ServerMessageType::BeginTransfer {
+                transfer_id,
+                total_size,
+                sha256,
+            } => {
+                let download = self
+                    .downloads
+                    .entry(transfer_id)
+                    .or_insert_with(|| IncomingTransfer::new(total_size, sha256));
+                let offset = download.offset();
+
+                if download.is_complete() {
+                    self.complete_download(transfer_id).await?;
+                } else {
+                    self.send(ClientMessageType::ResumeTransfer {
+                        transfer_id,
+                        offset,
+                    })
+                    .await?;
+                }
+            }
+
+            ServerMessageType::TransferChunk {
+                transfer_id,
+                index,
+                bytes,
+            } => {
+                let Some(download) = self.downloads.get_mut(&transfer_id) else {
+                    return self.send_error("This transfer does not exist").await;
+                };
+                if let Err(err) = download.push(index, &bytes) {
+                    return self.fail_download(transfer_id, &err.to_string()).await;
+                }
+                let is_complete = download.is_complete();
+
+                self.send(ClientMessageType::ChunkAck { transfer_id, index })
+                    .await?;
+                if is_complete {
+                    self.complete_download(transfer_id).await?;
+                }
+            }
+
+            ServerMessageType::Overlays { .. } => {
+                self.pending_overlays = Some(message);
+                self.forward_overlays().await?;
+            }
+
+            ServerMessageType::Fire | ServerMessageType::Error { .. } => {
+                self.messages.send(message).await?;
+            }
+
+            ServerMessageType::PartyDisbanded { party_id } => {
+                eprintln!("Party {party_id} was disbanded");
+            }
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Verify a downloaded media and make it available to the render loop.
+    async fn complete_download(&mut self, transfer_id: Uuid) -> anyhow::Result<()> {
+        let download = self.downloads.remove(&transfer_id).unwrap();
+        let media = MediaRef {
+            sha256: download.sha256(),
+            len: download.total_size(),
+        };
+
+        match download.finish() {
+            Ok(data) => {
+                self.cache_mut().insert(media, Arc::new(data));
+                self.missing_media.remove(&media);
+                self.send(ClientMessageType::TransferComplete { transfer_id })
+                    .await?;
+                self.forward_overlays().await
+            }
+            Err(err) => self.fail_download(transfer_id, &err.to_string()).await,
+        }
+    }
+
+    /// Give up on a download, along with the overlays waiting on it.
+    ///
+    /// The server leaves us out of their batch, and later batches are not held back by media that will never come.
+    async fn fail_download(&mut self, transfer_id: Uuid, reason: &str) -> anyhow::Result<()> {
+        self.downloads.remove(&transfer_id);
+        self.missing_media.clear();
+
+        let reason = format!("Transfer failed: {reason}");
+        eprintln!("{reason}");
+        if self.pending_overlays.take().is_some() {
+            self.send(ClientMessageType::RasterizationFailed { reason })
+                .await
+        } else {
+            self.send(ClientMessageType::Error { message: reason })
+                .await
+        }
+    }
+
+    /// Acknowledge the pending overlays and hand them to the render loop, once every media they need is here.
+    async fn forward_overlays(&mut self) -> anyhow::Result<()> {
+        if !self.missing_media.is_empty() {
+            return Ok(());
+        }
+        let Some(overlays) = self.pending_overlays.take() else {
+            return Ok(());
+        };
+
+        self.send(ClientMessageType::OverlaysAck).await?;
+        self.messages.send(overlays).await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, kind: ClientMessageType) -> anyhow::Result<()> {
+        let message = ClientMessage {
+            version: Version::CURRENT,
+            kind,
+        };
+        let bytes = self.codec.encode(&message)?;
+        self.socket.send(Message::Binary(bytes.into())).await?;
+        Ok(())
+    }
+
+    async fn send_error(&mut self, message: &str) -> anyhow::Result<()> {
+        eprintln!("{message}");
+        self.send(ClientMessageType::Error {
+            message: message.to_string(),
+        })
+        .await
+    }
+
+    /// Wait for the next message from the server, used before `run` takes over.
+    async fn receive(&mut self) -> anyhow::Result<ServerMessage> {
+        while let Some(message) = self.socket.next().await {
+            match message? {
+                Message::Binary(bytes) => return Ok(self.codec.decode(&bytes)?),
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+
+        anyhow::bail!("The server closed the connection")
+    }
+
+    fn cache(&self) -> std::sync::RwLockReadGuard<'_, MediaCache> {
+        self.media_cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn cache_mut(&self) -> std::sync::RwLockWriteGuard<'_, MediaCache> {
+        self.media_cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::{DisplayOptions, SenderInfo};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Server side of the websocket of a `Client`.
+    struct FakeServer {
+        codec: Codec,
+        socket: WebSocketStream<TcpStream>,
+    }
+
+    impl FakeServer {
+        async fn send(&mut self, kind: ServerMessageType) {
+            let message = ServerMessage {
+                version: Version::CURRENT,
+                sender: SenderInfo { id: Uuid::nil() },
+                kind,
+            };
+            let bytes = self.codec.encode(&message).unwrap();
+            self.socket
+                .send(Message::Binary(bytes.into()))
+                .await
+                .unwrap();
+        }
+
+        async fn receive(&mut self) -> ClientMessageType {
+            loop {
+                if let Message::Binary(bytes) = self.socket.next().await.unwrap().unwrap() {
+                    return self.codec.decode::<ClientMessage>(&bytes).unwrap().kind;
+                }
+            }
+        }
+    }
+
+    /// Connect a running `Client` to a fake server, returning the messages it forwards to the render loop.
+    async fn connect() -> (FakeServer, mpsc::Receiver<ServerMessage>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("ws://{}", listener.local_addr().unwrap());
+        let (messages_tx, messages_rx) = mpsc::channel(16);
+        let client = tokio::spawn(async move {
+            let media_cache = Arc::new(RwLock::new(MediaCache::default()));
+            Client::connect(&address, media_cache, messages_tx).await
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut server = FakeServer {
+            codec: Codec::default(),
+            socket: tokio_tungstenite::accept_async(stream).await.unwrap(),
+        };
+        assert!(matches!(
+            server.receive().await,
+            ClientMessageType::Hello {
+                kind: ClientKind::SplashScreen
+            }
+        ));
+        server
+            .send(ServerMessageType::Welcome {
+                client_id: Uuid::new_v4(),
+            })
+            .await;
+
+        let client = client.await.unwrap().unwrap();
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Keeps the events open for as long as the client runs
+            let _events_tx = events_tx;
+            client.run(events_rx).await
+        });
+        (server, messages_rx)
+    }
+
+    fn overlays() -> ServerMessageType {
+        ServerMessageType::Overlays {
+            overlays: Vec::new(),
+            options: DisplayOptions { timeout_ms: 1000 },
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_downloads_do_not_hold_back_later_batches() {
+        let (mut server, mut messages) = connect().await;
+        let data = b"media";
+        let media = MediaRef::of(data);
+        let transfer_id = media.transfer_id();
+
+        server
+            .send(ServerMessageType::HaveMedia { media: vec![media] })
+            .await;
+        server.send(overlays()).await;
+        assert!(matches!(
+            server.receive().await,
+            ClientMessageType::WantMedia { media: wanted } if wanted == [media]
+        ));
+
+        server
+            .send(ServerMessageType::BeginTransfer {
+                transfer_id,
+                total_size: media.len,
+                sha256: media.sha256,
+            })
+            .await;
+        assert!(matches!(
+            server.receive().await,
+            ClientMessageType::ResumeTransfer { offset: 0, .. }
+        ));
+
+        // The digest does not match once every byte is here
+        server
+            .send(ServerMessageType::TransferChunk {
+                transfer_id,
+                index: 0,
+                bytes: b"MEDIA".to_vec(),
+            })
+            .await;
+        assert!(matches!(
+            server.receive().await,
+            ClientMessageType::ChunkAck { index: 0, .. }
+        ));
+        assert!(matches!(
+            server.receive().await,
+            ClientMessageType::RasterizationFailed { .. }
+        ));
+
+        // The next batch does not wait on the media of the failed one
+        server.send(overlays()).await;
+        assert!(matches!(
+            server.receive().await,
+            ClientMessageType::OverlaysAck
+        ));
+        assert!(matches!(
+            messages.recv().await.unwrap().kind,
+            ServerMessageType::Overlays { .. }
+        ));
+    }
+}
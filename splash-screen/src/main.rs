@@ -1,164 +1,464 @@
-use std::{fs, str::FromStr, time::Duration};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::Duration,
+};
 
 use crate::{
-    compositor::Compositor,
-    overlay::{AnimatedOverlay, ImageOverlay, TextOverlay},
-    window::{SplashWindow, Win32Renderer, Win32Window},
+    client::Client,
+    window::{FrameOutput, HeadlessWindow, Renderer, SplashWindow},
 };
 
-use cosmic_text::{FontSystem, SwashCache};
-use ff::Overlay as LibOverlay;
-use tokio::time::Instant;
-use uuid::Uuid;
-
-mod compositor;
-mod frame;
+use clap::{Parser, ValueEnum};
+use ff::MediaCache;
+use render::{Compositor, RasterizedBatch, Rasterizer};
+use tokio::{sync::mpsc, time::Instant};
 
-mod overlay;
+mod client;
 mod window;
 
-/// Mock function to fake a message from the server
-fn receive_mock_message() -> ff::ServerMessage {
-    ff::ServerMessage {
-        version: ff::Version::from_str("0.1.0").unwrap(),
-        sender: ff::SenderInfo { id: Uuid::new_v4() },
-        kind: ff::ServerMessageType::Overlays {
-            overlays: vec![
-                LibOverlay::AnimatedImage {
-                    bytes: fs::read("john-walk.gif").unwrap(),
-                    offset_left: 800,
-                    offset_top: 0,
-                    z_index: 1000,
-                },
-                LibOverlay::Image {
-                    bytes: fs::read("bonk.png").unwrap(),
-                    offset_left: 0,
-                    offset_top: 0,
-                    z_index: 1010,
-                },
-                LibOverlay::Text {
-                    text: "Zoubida!".to_string(),
-                    size: 52,
-                    color: [255, 255, 255, 255],
-                    offset_left: 0,
-                    offset_top: 0,
-                    z_index: 1020,
-                },
-            ],
-            options: ff::DisplayOptions { timeout_ms: 3000 },
-        },
-    }
-}
+/// Server to connect to when none is given.
+const DEFAULT_ADDRESS: &str = "ws://127.0.0.1:9001";
 
-/// Adds overlays from `ServerMessage` to a `Compositor`
-pub fn add_overlays_from_message(
-    compositor: &mut Compositor,
-    font_system: &mut FontSystem,
-    swash_cache: &mut SwashCache,
-    message: ff::ServerMessageType,
-) -> anyhow::Result<()> {
-    if let ff::ServerMessageType::Overlays { overlays, .. } = message {
-        for overlay in overlays {
-            match overlay {
-                LibOverlay::Image {
-                    bytes,
-                    offset_left,
-                    offset_top,
-                    z_index,
-                } => {
-                    compositor.add_overlay(Box::new(ImageOverlay::from_bytes(
-                        &bytes,
-                        offset_left,
-                        offset_top,
-                        z_index,
-                    )));
-                }
+/// Show the overlays sent to a party, on top of everything else.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Link given by the creator of the party, e.g. `friendlyfire://join/<code>`
+    invitation_link: String,
 
-                LibOverlay::AnimatedImage {
-                    bytes,
-                    offset_left,
-                    offset_top,
-                    z_index,
-                } => {
-                    let t0 = Instant::now().elapsed().as_millis();
-                    compositor.add_overlay(Box::new(AnimatedOverlay::from_bytes(
-                        &bytes,
-                        offset_left,
-                        offset_top,
-                        z_index,
-                        t0,
-                    )));
-                }
+    /// Address of the relay server
+    #[arg(long, default_value = DEFAULT_ADDRESS)]
+    server: String,
 
-                LibOverlay::Text {
-                    text,
-                    size,
-                    color,
-                    offset_left,
-                    offset_top,
-                    z_index,
-                } => {
-                    compositor.add_overlay(Box::new(TextOverlay::from_bytes(
-                        font_system,
-                        swash_cache,
-                        &text,
-                        size,
-                        &color,
-                        offset_left,
-                        offset_top,
-                        z_index,
-                    )?));
-                }
-            }
+    /// Where the overlays are shown
+    #[arg(long, value_enum, default_value_t = Backend::native())]
+    backend: Backend,
+
+    /// Size of the headless framebuffer, as `<width>x<height>`
+    #[arg(long, default_value = "1920x1080", value_parser = parse_dimensions)]
+    size: (u32, u32),
+
+    /// Write every frame of the headless backend as a numbered PNG in this directory
+    #[arg(long, conflicts_with = "raw_stdout")]
+    png_dir: Option<PathBuf>,
+
+    /// Stream every frame of the headless backend to stdout, as raw RGBA8
+    #[arg(long)]
+    raw_stdout: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// Layered Win32 window, only available on Windows
+    Win32,
+    /// X11 window, only available with the `x11` feature
+    X11,
+    /// Wayland layer-shell surface, only available with the `wayland` feature
+    Wayland,
+    /// No window at all, see `HeadlessWindow`
+    Headless,
+}
+
+impl Backend {
+    /// Best backend for the current platform.
+    fn native() -> Self {
+        if cfg!(windows) {
+            Backend::Win32
+        } else if cfg!(feature = "wayland") && std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            Backend::Wayland
+        } else if cfg!(feature = "x11") {
+            Backend::X11
+        } else {
+            Backend::Headless
         }
     }
+}
+
+/// Parse `<width>x<height>` dimensions.
+fn parse_dimensions(dimensions: &str) -> Result<(u32, u32), String> {
+    dimensions
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .ok_or_else(|| "expected dimensions as `<width>x<height>`, e.g. `1920x1080`".to_string())
+}
+
+/// What happened in the render loop that the server should know about.
+pub enum RenderEvent {
+    /// Every overlay of the last batch was built into a `Frame`.
+    Rasterized,
+    /// The last batch could not be rasterized.
+    RasterizationFailed(String),
+}
 
-    Ok(())
+/// State of the last batch of overlays received from the server.
+enum BatchState {
+    /// No batch to show.
+    Idle,
+    /// The batch is being rasterized, it may already have been fired.
+    Rasterizing {
+        number: u64,
+        fired_at_ms: Option<u128>,
+    },
+    /// The batch is waiting to be fired.
+    Ready(RasterizedBatch),
+}
+
+/// Wait for the compositor to need a new `Frame`, forever if it does not.
+async fn wait_for_next_frame(delay_ms: Option<u128>) {
+    match delay_ms {
+        // The cast should not be an issue, I think...
+        Some(delay) => tokio::time::sleep(Duration::from_millis(delay as u64)).await,
+        // Nothing will change on screen by itself
+        None => std::future::pending().await,
+    }
 }
 
 /// Continuously renders `Frame` based on a time reference and
 /// presents them to the window at the cadence dicted by the compositor.
 ///
-/// This functions never returns, it is expected to run in an async task (`tokio::spawn`).
-/// Alongside to another task that receives `ServerMessage`.
-// TODO : Allow this function to get `ServerMessage` through a channel
-pub async fn run_render_loop(window: &mut Win32Window, compositor: &mut Compositor) {
+/// `ServerMessage` are received through `messages`, alongside to another task that talks to the server.
+/// `Overlays` are decoded and rasterized outside of the render loop, and shown once the `Fire` message is received.
+/// The end of the rasterization is reported through `events`.
+///
+/// The window is cleared once every overlay is gone, and the loop then idles until something new is to be shown.
+///
+/// This functions returns once `messages` is closed.
+pub async fn run_render_loop<W: SplashWindow + Renderer>(
+    window: &mut W,
+    compositor: &mut Compositor,
+    mut messages: mpsc::Receiver<ff::ServerMessage>,
+    events: mpsc::UnboundedSender<RenderEvent>,
+    media_cache: Arc<RwLock<MediaCache>>,
+) {
     let origin = Instant::now();
+    // Avoids clearing an already transparent window over and over
+    let mut is_cleared = false;
+
+    let rasterizer = Arc::new(Mutex::new(Rasterizer::default()));
+    let (rasterized_tx, mut rasterized_rx) = mpsc::unbounded_channel();
+    let mut batch = BatchState::Idle;
+    // Every batch is numbered, so that a batch replaced while being rasterized can be ignored
+    let mut batch_count = 0;
 
     loop {
         let timestamp_ms = origin.elapsed().as_millis();
-        let frame = compositor.render(timestamp_ms);
-        window.draw_frame(frame);
+        compositor.render(timestamp_ms);
+
+        if compositor.is_empty() {
+            if !is_cleared {
+                window.clear();
+                is_cleared = true;
+            }
+        } else if !compositor.damage().is_empty() {
+            window.draw_frame(&compositor.canvas, compositor.damage());
+            is_cleared = false;
+        }
 
-        let delay = compositor
-            .time_until_next_frame_ms(timestamp_ms)
-            .unwrap_or(200);
+        tokio::select! {
+            _ = wait_for_next_frame(compositor.time_until_next_frame_ms(timestamp_ms)) => {}
 
-        // The cast should not be an issue, I think...
-        tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+            message = messages.recv() => {
+                let Some(message) = message else {
+                    break;
+                };
+
+                match message.kind {
+                    ff::ServerMessageType::Overlays { overlays, options } => {
+                        batch_count += 1;
+                        batch = BatchState::Rasterizing {
+                            number: batch_count,
+                            fired_at_ms: None,
+                        };
+
+                        let rasterizer = rasterizer.clone();
+                        let media_cache = media_cache.clone();
+                        let rasterized_tx = rasterized_tx.clone();
+                        let number = batch_count;
+                        // Relative positions and sizes are resolved against our own canvas
+                        let canvas = compositor.dimensions();
+                        let task = tokio::task::spawn_blocking(move || {
+                            // A previous batch may have panicked while rasterizing, which does not leave them in a broken state
+                            let media_cache = media_cache.read().unwrap_or_else(PoisonError::into_inner);
+                            rasterizer
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .rasterize(overlays, options, &media_cache, canvas)
+                        });
+                        tokio::spawn(async move {
+                            // A panic is reported like any other failure, so that the batch does not wait for it forever
+                            let result = task
+                                .await
+                                .unwrap_or_else(|err| Err(anyhow::anyhow!("the rasterizer crashed: {err}")));
+                            // The render loop may be gone already, there is nothing to do about it
+                            let _ = rasterized_tx.send((number, result));
+                        });
+                    }
+
+                    ff::ServerMessageType::Fire => {
+                        let fired_at_ms = origin.elapsed().as_millis();
+                        match std::mem::replace(&mut batch, BatchState::Idle) {
+                            BatchState::Ready(ready) => ready.fire(compositor, fired_at_ms),
+                            BatchState::Rasterizing { number, .. } => {
+                                batch = BatchState::Rasterizing {
+                                    number,
+                                    fired_at_ms: Some(fired_at_ms),
+                                };
+                            }
+                            BatchState::Idle => eprintln!("Received Fire without any overlays"),
+                        }
+                    }
+
+                    ff::ServerMessageType::Error { message } => {
+                        eprintln!("Error from the server: {message}");
+                    }
+
+                    _ => {}
+                }
+            }
+
+            Some((number, result)) = rasterized_rx.recv() => {
+                let BatchState::Rasterizing { number: expected, fired_at_ms } = batch else {
+                    continue;
+                };
+                if number != expected {
+                    continue;
+                }
+
+                batch = BatchState::Idle;
+                // The client may be gone already, there is nothing to do about it
+                match result {
+                    Ok(ready) => {
+                        let _ = events.send(RenderEvent::Rasterized);
+                        match fired_at_ms {
+                            Some(fired_at_ms) => ready.fire(compositor, fired_at_ms),
+                            None => batch = BatchState::Ready(ready),
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("Could not rasterize overlays: {err:#}");
+                        let _ = events.send(RenderEvent::RasterizationFailed(format!("{err:#}")));
+                    }
+                }
+            }
+        }
     }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let mut font_system = FontSystem::new();
-    let mut swash_cache = SwashCache::new();
-
-    let mut window = Win32Window::create()?;
+/// Show the overlays on a window until `messages` is closed.
+async fn run_window<W: SplashWindow + Renderer>(
+    mut window: W,
+    messages: mpsc::Receiver<ff::ServerMessage>,
+    events: mpsc::UnboundedSender<RenderEvent>,
+    media_cache: Arc<RwLock<MediaCache>>,
+) {
     window.show();
 
     let (w, h) = window.dimensions();
     let mut compositor = Compositor::new(w, h);
 
-    let message = receive_mock_message();
-    add_overlays_from_message(
-        &mut compositor,
-        &mut font_system,
-        &mut swash_cache,
-        message.kind,
-    )?;
+    run_render_loop(&mut window, &mut compositor, messages, events, media_cache).await;
+    window.destroy();
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let media_cache = Arc::new(RwLock::new(MediaCache::default()));
+    let (messages_tx, messages_rx) = mpsc::channel(16);
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+    let mut client = Client::connect(&cli.server, media_cache.clone(), messages_tx).await?;
+    client.join(&cli.invitation_link).await?;
+    // Once the client is done, the render loop is too
+    let client = tokio::spawn(client.run(events_rx));
+
+    match cli.backend {
+        #[cfg(windows)]
+        Backend::Win32 => {
+            let window = window::Win32Window::create()?;
+            run_window(window, messages_rx, events_tx, media_cache).await;
+        }
+        #[cfg(not(windows))]
+        Backend::Win32 => anyhow::bail!("The Win32 backend is only available on Windows"),
+        #[cfg(feature = "x11")]
+        Backend::X11 => {
+            let window = window::X11Window::create()?;
+            run_window(window, messages_rx, events_tx, media_cache).await;
+        }
+        #[cfg(not(feature = "x11"))]
+        Backend::X11 => anyhow::bail!("The X11 backend requires the `x11` feature"),
+        #[cfg(feature = "wayland")]
+        Backend::Wayland => {
+            let window = window::WaylandWindow::create()?;
+            run_window(window, messages_rx, events_tx, media_cache).await;
+        }
+        #[cfg(not(feature = "wayland"))]
+        Backend::Wayland => anyhow::bail!("The Wayland backend requires the `wayland` feature"),
+        Backend::Headless => {
+            let output = match (cli.png_dir, cli.raw_stdout) {
+                (Some(directory), _) => FrameOutput::Png(directory),
+                (None, true) => FrameOutput::RawStdout,
+                (None, false) => FrameOutput::None,
+            };
+            let (width, height) = cli.size;
+            let window = HeadlessWindow::new(width, height, output)?;
+            run_window(window, messages_rx, events_tx, media_cache).await;
+        }
+    }
+
+    client.await?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ff::{SenderInfo, ServerMessage, ServerMessageType, Version};
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use uuid::Uuid;
 
-    run_render_loop(&mut window, &mut compositor).await;
+    use super::*;
+
+    /// Render loop running on a shown `HeadlessWindow`, fed by the returned channels.
+    struct Loop {
+        messages: mpsc::Sender<ServerMessage>,
+        events: mpsc::UnboundedReceiver<RenderEvent>,
+        task: tokio::task::JoinHandle<(HeadlessWindow, Compositor)>,
+    }
+
+    impl Loop {
+        fn start() -> Self {
+            let (messages_tx, messages_rx) = mpsc::channel(16);
+            let (events_tx, events) = mpsc::unbounded_channel();
+            let task = tokio::spawn(async move {
+                let mut window = HeadlessWindow::new(8, 8, FrameOutput::None).unwrap();
+                window.show();
+                let mut compositor = Compositor::new(8, 8);
+                let media_cache = Arc::new(RwLock::new(MediaCache::default()));
+                run_render_loop(
+                    &mut window,
+                    &mut compositor,
+                    messages_rx,
+                    events_tx,
+                    media_cache,
+                )
+                .await;
+                (window, compositor)
+            });
+
+            Self {
+                messages: messages_tx,
+                events,
+                task,
+            }
+        }
 
-    Ok(())
+        async fn send(&self, kind: ServerMessageType) {
+            let message = ServerMessage {
+                version: Version::CURRENT,
+                sender: SenderInfo { id: Uuid::nil() },
+                kind,
+            };
+            self.messages.send(message).await.unwrap();
+        }
+
+        /// Close the channel of messages, which stops the loop, and return what it rendered to.
+        async fn stop(self) -> (HeadlessWindow, Compositor) {
+            drop(self.messages);
+            self.task.await.unwrap()
+        }
+    }
+
+    /// A batch of a single opaque red square of 2x2 pixels, shown for `timeout_ms`.
+    fn red_square(timeout_ms: u32) -> ServerMessageType {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        ServerMessageType::Overlays {
+            overlays: vec![ff::Overlay::Image {
+                media: ff::Media::Inline(png),
+                offset_left: 0,
+                offset_top: 0,
+                z_index: 0,
+                start_ms: 0,
+                duration_ms: None,
+                transform: ff::Transform::default(),
+                position: None,
+            }],
+            options: ff::DisplayOptions { timeout_ms },
+        }
+    }
+
+    fn is_transparent(frame: &render::Frame) -> bool {
+        frame.buffer.iter().all(|&byte| byte == 0)
+    }
+
+    #[tokio::test]
+    async fn fired_batches_are_drawn() {
+        let mut render_loop = Loop::start();
+
+        render_loop.send(red_square(60_000)).await;
+        assert!(matches!(
+            render_loop.events.recv().await,
+            Some(RenderEvent::Rasterized)
+        ));
+        render_loop.send(ServerMessageType::Fire).await;
+        // Let the loop go through the Fire message
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (window, compositor) = render_loop.stop().await;
+        assert!(!compositor.is_empty());
+        let framebuffer = window.framebuffer();
+        assert_eq!(framebuffer.buffer[..4], [255, 0, 0, 255]);
+        assert!(!is_transparent(framebuffer));
+    }
+
+    #[tokio::test]
+    async fn the_window_is_cleared_once_the_batch_times_out() {
+        let mut render_loop = Loop::start();
+
+        render_loop.send(red_square(50)).await;
+        assert!(matches!(
+            render_loop.events.recv().await,
+            Some(RenderEvent::Rasterized)
+        ));
+        render_loop.send(ServerMessageType::Fire).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let (window, compositor) = render_loop.stop().await;
+        assert!(compositor.is_empty());
+        assert!(is_transparent(window.framebuffer()));
+        // Cleared when starting, drawn once, cleared once, and then nothing until the next batch
+        assert_eq!(window.presented(), 3);
+    }
+
+    #[tokio::test]
+    async fn rasterization_failures_are_reported() {
+        let mut render_loop = Loop::start();
+
+        let ServerMessageType::Overlays {
+            mut overlays,
+            options,
+        } = red_square(50)
+        else {
+            unreachable!();
+        };
+        if let ff::Overlay::Image { media, .. } = &mut overlays[0] {
+            *media = ff::Media::Inline(b"not an image".to_vec());
+        }
+        render_loop
+            .send(ServerMessageType::Overlays { overlays, options })
+            .await;
+        assert!(matches!(
+            render_loop.events.recv().await,
+            Some(RenderEvent::RasterizationFailed(_))
+        ));
+
+        let (window, _) = render_loop.stop().await;
+        assert!(is_transparent(window.framebuffer()));
+    }
 }
@@ -0,0 +1,138 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use image::{ImageBuffer, Rgba};
+use render::{Frame, Rect};
+
+use crate::window::traits::{Renderer, SplashWindow};
+
+/// Size of the framebuffer when none is given, a common screen size.
+const DEFAULT_DIMENSIONS: (u32, u32) = (1920, 1080);
+
+/// Where the frames presented to a `HeadlessWindow` end up, on top of its in-memory framebuffer.
+pub enum FrameOutput {
+    /// Only keep the last frame in memory.
+    None,
+    /// Write each frame as a numbered PNG (`frame-000000.png`, `frame-000001.png`, ...) in a directory.
+    Png(PathBuf),
+    /// Write each frame to stdout as raw straight RGBA8, one after the other with no separator.
+    /// Every frame is `width * height * 4` bytes long.
+    RawStdout,
+}
+
+/// A window that is never shown on any display, implementing the `SplashWindow` trait.
+///
+/// Presented frames are kept in an in-memory framebuffer and optionally written out,
+/// allowing the splash-screen to run on machines without a display (servers, tests, screenshots).
+pub struct HeadlessWindow {
+    framebuffer: Frame,
+    output: FrameOutput,
+    /// Number of frames presented so far, used to number the PNGs.
+    presented: u64,
+    /// Frames are only written out while the window is shown.
+    is_visible: bool,
+}
+
+impl HeadlessWindow {
+    pub fn new(width: u32, height: u32, output: FrameOutput) -> anyhow::Result<Self> {
+        if let FrameOutput::Png(directory) = &output {
+            fs::create_dir_all(directory)?;
+        }
+
+        Ok(Self {
+            framebuffer: Frame::new(0, 0, width, height, 0),
+            output,
+            presented: 0,
+            is_visible: false,
+        })
+    }
+
+    /// Last frame presented.
+    #[cfg(test)]
+    pub fn framebuffer(&self) -> &Frame {
+        &self.framebuffer
+    }
+
+    /// Number of frames presented so far, cleared ones included.
+    #[cfg(test)]
+    pub fn presented(&self) -> u64 {
+        self.presented
+    }
+
+    /// Write the framebuffer to the output, if the window is visible.
+    fn present(&mut self) -> anyhow::Result<()> {
+        if !self.is_visible {
+            return Ok(());
+        }
+
+        match &self.output {
+            FrameOutput::None => {}
+            FrameOutput::Png(directory) => {
+                write_png(&self.framebuffer, directory, self.presented)?;
+            }
+            FrameOutput::RawStdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&self.framebuffer.to_straight_rgba())?;
+                stdout.flush()?;
+            }
+        }
+
+        self.presented += 1;
+        Ok(())
+    }
+}
+
+/// Write a frame as `frame-<number>.png` in `directory`.
+fn write_png(frame: &Frame, directory: &Path, number: u64) -> anyhow::Result<()> {
+    let image: ImageBuffer<Rgba<u8>, _> =
+        ImageBuffer::from_raw(frame.width, frame.height, frame.to_straight_rgba())
+            .ok_or_else(|| anyhow::anyhow!("frame buffer does not match its dimensions"))?;
+    image.save(directory.join(format!("frame-{number:06}.png")))?;
+    Ok(())
+}
+
+impl SplashWindow for HeadlessWindow {
+    fn create() -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let (width, height) = DEFAULT_DIMENSIONS;
+        Self::new(width, height, FrameOutput::None)
+    }
+
+    fn show(&mut self) {
+        self.is_visible = true;
+    }
+
+    fn hide(&mut self) {
+        self.is_visible = false;
+    }
+
+    fn destroy(&mut self) {
+        self.is_visible = false;
+    }
+
+    fn clear(&mut self) {
+        self.framebuffer.clear();
+        if let Err(err) = self.present() {
+            eprintln!("Could not write frame: {err:#}");
+        }
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.framebuffer.width, self.framebuffer.height)
+    }
+}
+
+impl Renderer for HeadlessWindow {
+    fn draw_frame(&mut self, frame: &Frame, _damage: &[Rect]) {
+        // Every frame is written out as a whole anyway
+        self.framebuffer.clone_from(frame);
+        if let Err(err) = self.present() {
+            eprintln!("Could not write frame: {err:#}");
+        }
+    }
+}
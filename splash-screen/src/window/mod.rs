@@ -1,8 +1,21 @@
+mod headless;
+#[cfg(windows)]
 mod rendering;
 mod traits;
+#[cfg(feature = "wayland")]
+mod wayland;
+#[cfg(windows)]
 mod win32;
+#[cfg(windows)]
 mod win32error;
+#[cfg(feature = "x11")]
+mod x11;
 
-pub use rendering::Win32Renderer;
-pub use traits::SplashWindow;
+pub use headless::{FrameOutput, HeadlessWindow};
+pub use traits::{Renderer, SplashWindow};
+#[cfg(feature = "wayland")]
+pub use wayland::WaylandWindow;
+#[cfg(windows)]
 pub use win32::Win32Window;
+#[cfg(feature = "x11")]
+pub use x11::X11Window;
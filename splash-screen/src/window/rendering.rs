@@ -1,20 +1,16 @@
 use std::{ffi::c_void, mem::size_of, ptr};
 
+use render::{Frame, Rect};
 use windows::Win32::{Foundation::*, Graphics::Gdi::*, UI::WindowsAndMessaging::*};
 
-use crate::{frame::Frame, window::win32::Win32Window};
+use crate::window::{traits::Renderer, win32::Win32Window};
 
-/// Abstracted renderer capable of presenting a `Frame` onto a `SplashWindow`.
-pub trait Win32Renderer {
-    /// Render a complete `Frame` to the layered window
-    fn draw_frame(&self, frame: &Frame);
-}
-
-impl Win32Renderer for Win32Window {
-    fn draw_frame(&self, frame: &Frame) {
-        // windows uses pre-multiplied BGRA
+impl Renderer for Win32Window {
+    fn draw_frame(&mut self, frame: &Frame, _damage: &[Rect]) {
+        // UpdateLayeredWindow needs the whole bitmap, even when given a dirty rectangle
+        // windows uses pre-multiplied BGRA, frames are already premultiplied
         // https://stackoverflow.com/a/74925357
-        let bgra = rgba_to_premultiplied_bgra(&frame.buffer);
+        let bgra = rgba_to_bgra(&frame.buffer);
 
         unsafe {
             let mem_dc = create_compatible_dc();
@@ -28,21 +24,12 @@ impl Win32Renderer for Win32Window {
     }
 }
 
-/// Convert an RGBA buffer into premultiplied BGRA.
-pub fn rgba_to_premultiplied_bgra(src: &[u8]) -> Vec<u8> {
+/// Convert a premultiplied RGBA buffer into premultiplied BGRA.
+pub fn rgba_to_bgra(src: &[u8]) -> Vec<u8> {
     let mut bgra = Vec::with_capacity(src.len());
 
     for px in src.chunks_exact(4) {
-        let (r, g, b, a) = (px[0] as u32, px[1] as u32, px[2] as u32, px[3] as u32);
-
-        if a == 0 {
-            bgra.extend_from_slice(&[0, 0, 0, 0]);
-        } else {
-            let r_p = ((r * a + 127) / 255) as u8;
-            let g_p = ((g * a + 127) / 255) as u8;
-            let b_p = ((b * a + 127) / 255) as u8;
-            bgra.extend_from_slice(&[b_p, g_p, r_p, a as u8]);
-        }
+        bgra.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
     }
 
     bgra
@@ -141,40 +128,18 @@ unsafe fn cleanup_dc(mem_dc: HDC, dib: HBITMAP, old_obj: HGDIOBJ) {
     }
 }
 
-/// Clear a layered window by drawing a fully transparent pixel.
-pub fn clear(hwnd: HWND) {
+/// Clear a layered window by drawing a fully transparent bitmap of its size.
+///
+/// The window keeps its size and position, so that the next frame does not have to restore them.
+pub fn clear(hwnd: HWND, width: u32, height: u32) {
+    let pixels = vec![0u8; width as usize * height as usize * 4];
+
     unsafe {
         let mem_dc = create_compatible_dc();
-
-        let pixel = [0u8; 4];
-        let dib = create_dib_section(mem_dc, 1, 1, &pixel);
+        let dib = create_dib_section(mem_dc, width, height, &pixels);
         let old_obj = SelectObject(mem_dc, dib);
 
-        let blend = BLENDFUNCTION {
-            BlendOp: AC_SRC_OVER as u8,
-            BlendFlags: 0,
-            SourceConstantAlpha: 255,
-            AlphaFormat: AC_SRC_ALPHA as u8,
-        };
-
-        let pos = POINT { x: 0, y: 0 };
-        let size = SIZE { cx: 1, cy: 1 };
-        let src = POINT { x: 0, y: 0 };
-
-        let screen_dc = GetDC(HWND(0));
-        UpdateLayeredWindow(
-            hwnd,
-            screen_dc,
-            Some(&pos),
-            Some(&size),
-            mem_dc,
-            Some(&src),
-            COLORREF(0),
-            Some(&blend),
-            ULW_ALPHA,
-        )
-        .unwrap();
-        ReleaseDC(HWND(0), screen_dc);
+        update_layered_window(hwnd, mem_dc, width, height);
 
         cleanup_dc(mem_dc, dib, old_obj);
     }
@@ -1,3 +1,5 @@
+use render::{Frame, Rect};
+
 pub trait SplashWindow {
     /// Create a transparent, borderless, always-on-top window.
     fn create() -> anyhow::Result<Self>
@@ -19,3 +21,12 @@ pub trait SplashWindow {
     /// Returns the size of the fullscreen windows (same as the primary monitor size)
     fn dimensions(&self) -> (u32, u32);
 }
+
+/// Abstracted renderer capable of presenting a `Frame` onto a `SplashWindow`.
+pub trait Renderer {
+    /// Render a complete `Frame` to the window.
+    ///
+    /// Only the `damage` regions changed since the previous `Frame`, backends able to update
+    /// parts of the window can limit themselves to those.
+    fn draw_frame(&mut self, frame: &Frame, damage: &[Rect]);
+}
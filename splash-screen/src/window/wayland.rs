@@ -0,0 +1,372 @@
+use render::{Frame, Rect};
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_dispatch2, delegate_registry,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    shell::{
+        WaylandSurface,
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+    },
+    shm::{
+        Shm, ShmHandler,
+        slot::{Buffer, SlotPool},
+    },
+};
+use wayland_client::{
+    Connection, EventQueue, QueueHandle,
+    globals::registry_queue_init,
+    protocol::{wl_output, wl_shm, wl_surface},
+};
+
+use crate::window::traits::{Renderer, SplashWindow};
+
+/// A fullscreen `wlr-layer-shell` surface on the overlay layer, that implement the `SplashWindow` trait.
+///
+/// Supported by most compositors but GNOME (sway, Hyprland, KDE, weston, ...).
+pub struct WaylandWindow {
+    connection: Connection,
+    event_queue: EventQueue<State>,
+    state: State,
+}
+
+/// Everything the Wayland event handlers need access to.
+struct State {
+    registry_state: RegistryState,
+    output_state: OutputState,
+    shm: Shm,
+    /// Both are released once the window is destroyed.
+    pool: Option<SlotPool>,
+    layer: Option<LayerSurface>,
+
+    /// Size given by the compositor, `None` until the surface is first configured.
+    size: Option<(u32, u32)>,
+    /// Buffers can only be attached once the compositor configured the surface, which it does again
+    /// every time the surface is mapped.
+    is_configured: bool,
+    /// Buffer currently attached to the surface, released by the compositor once it is replaced.
+    buffer: Option<Buffer>,
+    /// Frames are only attached to the surface while it is shown.
+    is_visible: bool,
+}
+
+impl SplashWindow for WaylandWindow {
+    fn create() -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        // Connects to the compositor given by `$WAYLAND_DISPLAY`
+        let connection = Connection::connect_to_env()?;
+        let (globals, event_queue) = registry_queue_init(&connection)?;
+        let qh = event_queue.handle();
+
+        let compositor = CompositorState::bind(&globals, &qh)?;
+        let layer_shell = LayerShell::bind(&globals, &qh)
+            .map_err(|_| anyhow::anyhow!("The compositor does not support wlr-layer-shell"))?;
+        let shm = Shm::bind(&globals, &qh)?;
+
+        let surface = compositor.create_surface(&qh);
+        let layer = layer_shell.create_layer_surface(
+            &qh,
+            surface,
+            Layer::Overlay,
+            Some("friendlyfire-splash-screen"),
+            None,
+        );
+        // Stretched over the whole output, on top of panels and without pushing them away
+        layer.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
+        layer.set_size(0, 0);
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+
+        // An empty input region lets every click go through the surface
+        let region = Region::new(&compositor)?;
+        layer
+            .wl_surface()
+            .set_input_region(Some(region.wl_region()));
+
+        // The first commit, without any buffer, asks the compositor for the size of the surface
+        layer.commit();
+
+        let pool = SlotPool::new(4, &shm)?;
+        let state = State {
+            registry_state: RegistryState::new(&globals),
+            output_state: OutputState::new(&globals, &qh),
+            shm,
+            pool: Some(pool),
+            layer: Some(layer),
+            size: None,
+            is_configured: false,
+            buffer: None,
+            is_visible: false,
+        };
+        let mut window = Self {
+            connection,
+            event_queue,
+            state,
+        };
+        window.wait_for_configure()?;
+
+        Ok(window)
+    }
+
+    fn show(&mut self) {
+        let Some(layer) = &self.state.layer else {
+            return;
+        };
+        if !self.state.is_configured {
+            // Once unmapped, the surface is mapped again like a new one: a commit without any buffer, then a configure
+            layer.commit();
+            if let Err(err) = self.wait_for_configure() {
+                eprintln!("Could not map the Wayland surface again: {err}");
+                return;
+            }
+        }
+        self.state.is_visible = true;
+    }
+
+    fn hide(&mut self) {
+        self.state.is_visible = false;
+        let Some(layer) = &self.state.layer else {
+            return;
+        };
+        // Attaching no buffer unmaps the surface
+        layer.wl_surface().attach(None, 0, 0);
+        layer.commit();
+        self.state.is_configured = false;
+        self.state.buffer = None;
+        self.dispatch();
+    }
+
+    fn destroy(&mut self) {
+        self.hide();
+        // Dropping them destroys the surface and unmaps the memory shared with the compositor
+        self.state.layer = None;
+        self.state.pool = None;
+        self.dispatch();
+    }
+
+    fn clear(&mut self) {
+        // The surface stays mapped, so that the next frame can be presented right away
+        let (width, height) = self.dimensions();
+        self.present(&[Rect::new(0, 0, width, height)], |canvas| canvas.fill(0));
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.state.size.unwrap_or_default()
+    }
+}
+
+impl Renderer for WaylandWindow {
+    fn draw_frame(&mut self, frame: &Frame, damage: &[Rect]) {
+        let width = self.dimensions().0 as usize;
+        // Buffers are not reused, so they are filled as a whole, but the compositor only has to update the damage
+        self.present(damage, |canvas| {
+            canvas.fill(0);
+
+            // The frame is clipped to the surface, should the compositor have resized it
+            let columns = width.min(frame.width as usize);
+            let src_rows = frame.buffer.chunks_exact(frame.width as usize * 4);
+            let dst_rows = canvas.chunks_exact_mut(width * 4);
+            for (src, dst) in src_rows.zip(dst_rows) {
+                rgba_to_argb(&src[..columns * 4], &mut dst[..columns * 4]);
+            }
+        });
+    }
+}
+
+impl WaylandWindow {
+    /// Attach a new buffer to the surface, whose pixels are filled by `fill` (ARGB8888).
+    /// Only the `damage` regions are updated by the compositor.
+    fn present(&mut self, damage: &[Rect], fill: impl FnOnce(&mut [u8])) {
+        let (width, height) = self.dimensions();
+        let (Some(layer), Some(pool)) = (&self.state.layer, &mut self.state.pool) else {
+            return;
+        };
+        if !self.state.is_visible {
+            return;
+        }
+
+        let stride = width as i32 * 4;
+        let (buffer, canvas) = match pool.create_buffer(
+            width as i32,
+            height as i32,
+            stride,
+            wl_shm::Format::Argb8888,
+        ) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                eprintln!("Could not create a Wayland buffer: {err}");
+                return;
+            }
+        };
+        fill(canvas);
+
+        let surface = layer.wl_surface();
+        for rect in damage {
+            surface.damage_buffer(rect.left, rect.top, rect.width as i32, rect.height as i32);
+        }
+        if let Err(err) = buffer.attach_to(surface) {
+            eprintln!("Could not attach the Wayland buffer: {err}");
+            return;
+        }
+        layer.commit();
+        // The previous buffer is freed once the compositor releases it
+        self.state.buffer = Some(buffer);
+
+        self.dispatch();
+    }
+
+    /// Wait for the compositor to configure the surface after a commit without any buffer.
+    fn wait_for_configure(&mut self) -> anyhow::Result<()> {
+        while !self.state.is_configured {
+            anyhow::ensure!(
+                self.state.layer.is_some(),
+                "the layer surface was closed by the compositor"
+            );
+            self.event_queue.blocking_dispatch(&mut self.state)?;
+        }
+        Ok(())
+    }
+
+    /// Send our requests and handle the events received so far, without blocking.
+    fn dispatch(&mut self) {
+        if let Err(err) = self.connection.flush() {
+            eprintln!("Wayland connection failed: {err}");
+            return;
+        }
+        if let Some(guard) = self.event_queue.prepare_read() {
+            // Fails with `WouldBlock` when there is nothing to read
+            let _ = guard.read();
+        }
+        if let Err(err) = self.event_queue.dispatch_pending(&mut self.state) {
+            eprintln!("Wayland dispatch failed: {err}");
+        }
+    }
+}
+
+/// Convert a premultiplied RGBA row into ARGB8888, which is little-endian as per `wl_shm`.
+fn rgba_to_argb(src: &[u8], dst: &mut [u8]) {
+    for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+    }
+}
+
+impl LayerShellHandler for State {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+        // e.g. the output went away, nothing will be shown anymore
+        eprintln!("The compositor closed the layer surface");
+        self.is_visible = false;
+        self.layer = None;
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        self.size = Some(configure.new_size);
+        self.is_configured = true;
+    }
+}
+
+impl CompositorHandler for State {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_factor: i32,
+    ) {
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_transform: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for State {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl ShmHandler for State {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for State {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+
+    registry_handlers![OutputState];
+}
+
+delegate_registry!(State);
+delegate_dispatch2!(State);
@@ -3,12 +3,13 @@ use windows::{
     core::*,
 };
 
-use crate::window::{traits::SplashWindow, win32error::Win32Error};
+use crate::window::{rendering, traits::SplashWindow, win32error::Win32Error};
 
 /// A Win32 window that implement the `SplashWindow` trait
 pub struct Win32Window {
     pub handle: HWND,
-    pub thread: std::thread::JoinHandle<()>,
+    /// Thread owning the window and running its message loop, `None` once destroyed.
+    pub thread: Option<std::thread::JoinHandle<()>>,
 }
 
 impl SplashWindow for Win32Window {
@@ -37,7 +38,10 @@ impl SplashWindow for Win32Window {
         });
 
         let handle = rx.recv()?; // Wait for the thread to create the window
-        Ok(Self { handle, thread })
+        Ok(Self {
+            handle,
+            thread: Some(thread),
+        })
     }
 
     fn show(&mut self) {
@@ -49,11 +53,16 @@ impl SplashWindow for Win32Window {
     }
 
     fn destroy(&mut self) {
-        unsafe { DestroyWindow(self.handle).unwrap() }
+        // Only the thread owning the window can destroy it, `WM_CLOSE` makes it do so
+        unsafe { PostMessageA(self.handle, WM_CLOSE, WPARAM(0), LPARAM(0)).unwrap() };
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 
     fn clear(&mut self) {
-        todo!()
+        let (width, height) = self.dimensions();
+        rendering::clear(self.handle, width, height);
     }
 
     fn dimensions(&self) -> (u32, u32) {
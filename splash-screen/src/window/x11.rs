@@ -0,0 +1,225 @@
+use render::{Frame, Rect};
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        shape::{self, ConnectionExt as _},
+        xproto::{
+            ClipOrdering, ColormapAlloc, ConfigureWindowAux, ConnectionExt as _, CreateGCAux,
+            CreateWindowAux, ImageFormat, ImageOrder, StackMode, VisualClass, Visualid,
+            WindowClass,
+        },
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::window::traits::{Renderer, SplashWindow};
+
+/// Depth of the ARGB visual, the extra 8 bits over the usual 24 are the alpha channel.
+const ARGB_DEPTH: u8 = 32;
+
+/// A fullscreen X11 window that implement the `SplashWindow` trait.
+///
+/// Transparency relies on a 32-bit ARGB visual, which needs a compositor to be running (e.g. picom),
+/// without one the transparent parts of the window show up black.
+pub struct X11Window {
+    connection: RustConnection,
+    window: u32,
+    gc: u32,
+    width: u16,
+    height: u16,
+    /// Byte order of the pixels expected by the server.
+    image_order: ImageOrder,
+}
+
+impl SplashWindow for X11Window {
+    fn create() -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        // Connects to the display given by `$DISPLAY`
+        let (connection, screen_num) = x11rb::connect(None)?;
+        let screen = &connection.setup().roots[screen_num];
+        let (root, width, height) = (screen.root, screen.width_in_pixels, screen.height_in_pixels);
+        let visual = find_argb_visual(&connection, screen_num)
+            .ok_or_else(|| anyhow::anyhow!("The X server has no 32-bit ARGB visual"))?;
+
+        // Windows whose depth differs from their parent must bring their own colormap and border
+        let colormap = connection.generate_id()?;
+        connection.create_colormap(ColormapAlloc::NONE, colormap, root, visual)?;
+
+        let window = connection.generate_id()?;
+        let attributes = CreateWindowAux::new()
+            .background_pixel(0) // fully transparent
+            .border_pixel(0)
+            .colormap(colormap)
+            // Not managed by the window manager : no decorations, no taskbar entry, no focus
+            .override_redirect(1);
+        connection.create_window(
+            ARGB_DEPTH,
+            window,
+            root,
+            0,
+            0,
+            width,
+            height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            visual,
+            &attributes,
+        )?;
+
+        // An empty input shape lets every click go through the window
+        connection.shape_rectangles(
+            shape::SO::SET,
+            shape::SK::INPUT,
+            ClipOrdering::UNSORTED,
+            window,
+            0,
+            0,
+            &[],
+        )?;
+
+        let gc = connection.generate_id()?;
+        connection.create_gc(gc, window, &CreateGCAux::new())?;
+        connection.flush()?;
+
+        let image_order = connection.setup().image_byte_order;
+        Ok(Self {
+            connection,
+            window,
+            gc,
+            width,
+            height,
+            image_order,
+        })
+    }
+
+    fn show(&mut self) {
+        let result = self.connection.map_window(self.window).and_then(|_| {
+            self.connection.configure_window(
+                self.window,
+                &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+            )
+        });
+        self.flush(result.map(|_| ()));
+    }
+
+    fn hide(&mut self) {
+        let result = self.connection.unmap_window(self.window);
+        self.flush(result.map(|_| ()));
+    }
+
+    fn destroy(&mut self) {
+        let result = self.connection.destroy_window(self.window);
+        self.flush(result.map(|_| ()));
+    }
+
+    fn clear(&mut self) {
+        // Fills the whole window with its (transparent) background
+        let result = self.connection.clear_area(false, self.window, 0, 0, 0, 0);
+        self.flush(result.map(|_| ()));
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width as u32, self.height as u32)
+    }
+}
+
+impl Renderer for X11Window {
+    fn draw_frame(&mut self, frame: &Frame, damage: &[Rect]) {
+        // Only the damaged regions are sent, the server keeps the rest of the window as is
+        let mut result = Ok(());
+        for rect in damage {
+            result = self.put_rect(frame, rect);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.flush(result);
+    }
+}
+
+impl X11Window {
+    /// Send the pixels of `frame` inside `rect` to the window.
+    fn put_rect(&self, frame: &Frame, rect: &Rect) -> Result<(), x11rb::errors::ConnectionError> {
+        // A single request cannot hold a whole screen worth of pixels, the region is sent in tiles:
+        // bands of rows, themselves split in columns should a single row be too large
+        // Leaves some room for the header of the request
+        let max_pixels = ((self.connection.maximum_request_bytes() - 64) / 4) as u32;
+        let max_columns = rect.width.min(max_pixels).max(1);
+        let max_rows = max_pixels / max_columns;
+
+        for left in (0..rect.width).step_by(max_columns as usize) {
+            for top in (0..rect.height).step_by(max_rows as usize) {
+                let tile = Rect::new(
+                    rect.left + left as i32,
+                    rect.top + top as i32,
+                    max_columns.min(rect.width - left),
+                    max_rows.min(rect.height - top),
+                );
+                self.put_tile(frame, &tile)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the pixels of `frame` inside `tile` to the window, in a single request.
+    fn put_tile(&self, frame: &Frame, tile: &Rect) -> Result<(), x11rb::errors::ConnectionError> {
+        let row_size = tile.width as usize * 4;
+        let mut rgba = Vec::with_capacity(row_size * tile.height as usize);
+        for row in tile.top as usize..tile.top as usize + tile.height as usize {
+            let start = (row * frame.width as usize + tile.left as usize) * 4;
+            rgba.extend_from_slice(&frame.buffer[start..start + row_size]);
+        }
+
+        self.connection.put_image(
+            ImageFormat::Z_PIXMAP,
+            self.window,
+            self.gc,
+            tile.width as u16,
+            tile.height as u16,
+            (frame.offset_left + tile.left) as i16,
+            (frame.offset_top + tile.top) as i16,
+            0,
+            ARGB_DEPTH,
+            &rgba_to_argb(&rgba, self.image_order),
+        )?;
+        Ok(())
+    }
+
+    /// Send the pending requests to the server, logging any error along the way.
+    fn flush(&self, result: Result<(), x11rb::errors::ConnectionError>) {
+        if let Err(err) = result.and_then(|_| self.connection.flush()) {
+            eprintln!("X11 request failed: {err}");
+        }
+    }
+}
+
+/// Find a TrueColor visual with an alpha channel.
+fn find_argb_visual(connection: &RustConnection, screen_num: usize) -> Option<Visualid> {
+    connection.setup().roots[screen_num]
+        .allowed_depths
+        .iter()
+        .filter(|depth| depth.depth == ARGB_DEPTH)
+        .flat_map(|depth| &depth.visuals)
+        .find(|visual| visual.class == VisualClass::TRUE_COLOR)
+        .map(|visual| visual.visual_id)
+}
+
+/// Convert a premultiplied RGBA buffer into premultiplied ARGB pixels, in the byte order of the server.
+fn rgba_to_argb(src: &[u8], order: ImageOrder) -> Vec<u8> {
+    let mut argb = Vec::with_capacity(src.len());
+
+    for px in src.chunks_exact(4) {
+        let (r, g, b, a) = (px[0] as u32, px[1] as u32, px[2] as u32, px[3] as u32);
+        let pixel = (a << 24) | (r << 16) | (g << 8) | b;
+
+        if order == ImageOrder::LSB_FIRST {
+            argb.extend_from_slice(&pixel.to_le_bytes());
+        } else {
+            argb.extend_from_slice(&pixel.to_be_bytes());
+        }
+    }
+
+    argb
+}